@@ -0,0 +1,18 @@
+#!/bin/sh
+# Downloads the GraphiQL assets embedded by the feature `bundled-graphiql`
+# into the specified directory (the directory of this script by default).
+#
+# The downloaded files are committed to the repository; the build never downloads them.
+# The versions are the same as the ones used by `juniper::http::graphiql::graphiql_source`.
+
+set -eu
+
+cd "${1:-$(dirname "$0")}"
+
+CDNJS=https://cdnjs.cloudflare.com/ajax/libs
+
+curl -fsSL -o fetch.js "$CDNJS/fetch/2.0.3/fetch.js"
+curl -fsSL -o react.production.min.js "$CDNJS/react/16.2.0/umd/react.production.min.js"
+curl -fsSL -o react-dom.production.min.js "$CDNJS/react-dom/16.2.0/umd/react-dom.production.min.js"
+curl -fsSL -o graphiql.min.js "$CDNJS/graphiql/0.11.11/graphiql.min.js"
+curl -fsSL -o graphiql.css "$CDNJS/graphiql/0.10.2/graphiql.css"
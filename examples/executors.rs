@@ -50,9 +50,17 @@ fn main() {
             CpuPool::new_num_cpus(),
         ));
 
+    let thread_pool_endpoint = syntax::segment("thread_pool")
+        .map(|| MyContext { _priv: () })
+        .wrap(execute::thread_pool(
+            schema.clone(),
+            execute::ThreadPoolConfig::new().name_prefix("graphql-"),
+        ));
+
     let endpoint = current_thread_endpoint
         .or(nonblocking_endpoint)
-        .or(cpupool_endpoint);
+        .or(cpupool_endpoint)
+        .or(thread_pool_endpoint);
 
     info!("Listening on http://127.0.0.1:4000/");
     finchers::server::start(endpoint)
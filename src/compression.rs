@@ -0,0 +1,216 @@
+//! Compression of the response body.
+
+#[cfg(feature = "brotli")]
+use brotli;
+use flate2;
+use flate2::write::{GzEncoder, ZlibEncoder};
+use http::header::{self, HeaderMap};
+use std::io;
+use std::io::Write;
+
+/// The configuration of the compression of GraphQL responses.
+///
+/// The content coding is chosen from `gzip` and `deflate` (and `br`, if the feature
+/// `brotli` is enabled) based on the `Accept-Encoding` header of the request.
+/// The responses smaller than the threshold are sent without compression.
+#[derive(Debug, Clone, Copy)]
+pub struct Compression {
+    min_size: usize,
+    level: u32,
+}
+
+impl Default for Compression {
+    fn default() -> Compression {
+        Compression {
+            min_size: 1024,
+            level: 6,
+        }
+    }
+}
+
+impl Compression {
+    /// Creates a new `Compression` with the default values.
+    ///
+    /// By default, the responses smaller than 1 KiB are not compressed.
+    pub fn new() -> Compression {
+        Compression::default()
+    }
+
+    /// Sets the minimum size of the response body to be compressed, in bytes.
+    pub fn min_size(self, min_size: usize) -> Compression {
+        Compression { min_size, ..self }
+    }
+
+    /// Sets the compression level, from 0 (fastest) to 9 (best).
+    pub fn level(self, level: u32) -> Compression {
+        assert!(level <= 9, "level must be in the range of 0 to 9");
+        Compression { level, ..self }
+    }
+
+    /// Chooses the content coding to be applied to the response body.
+    pub(crate) fn negotiate(&self, headers: &HeaderMap, len: usize) -> Option<Encoding> {
+        if len < self.min_size {
+            return None;
+        }
+
+        // The explicitly listed codings take precedence over `*`, which only applies
+        // to the codings not listed elsewhere in the header (RFC 7231, section 5.3.4).
+        let mut explicit: Vec<(Encoding, f32)> = vec![];
+        let mut wildcard: Option<f32> = None;
+        let codings = headers
+            .get_all(header::ACCEPT_ENCODING)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','));
+        for coding in codings {
+            let mut params = coding.split(';').map(str::trim);
+            let name = params.next().unwrap_or("");
+            let quality = params
+                .filter(|param| param.starts_with("q="))
+                .map(|param| &param[2..])
+                .filter_map(|q| q.parse::<f32>().ok())
+                .next()
+                .unwrap_or(1.0);
+            if name == "*" {
+                wildcard = Some(quality);
+            } else if let Some(encoding) = Encoding::from_name(name) {
+                explicit.push((encoding, quality));
+            }
+        }
+
+        let mut candidate: Option<(Encoding, f32)> = None;
+        for &encoding in Encoding::ALL {
+            let quality = explicit
+                .iter()
+                .find(|&&(e, _)| e == encoding)
+                .map(|&(_, q)| q)
+                .or(wildcard)
+                .unwrap_or(0.0);
+            if quality <= 0.0 {
+                continue;
+            }
+            // `Encoding::ALL` is in the order of preference, so the ties are kept.
+            let is_preferred = match candidate {
+                Some((_, q)) => quality > q,
+                None => true,
+            };
+            if is_preferred {
+                candidate = Some((encoding, quality));
+            }
+        }
+        candidate.map(|(encoding, _)| encoding)
+    }
+
+    pub(crate) fn encode(&self, encoding: Encoding, body: &[u8]) -> io::Result<Vec<u8>> {
+        let level = flate2::Compression::new(self.level);
+        match encoding {
+            #[cfg(feature = "brotli")]
+            Encoding::Brotli => {
+                let mut encoder = brotli::CompressorWriter::new(vec![], 4096, self.level + 2, 22);
+                encoder.write_all(body)?;
+                Ok(encoder.into_inner())
+            }
+            Encoding::Gzip => {
+                let mut encoder = GzEncoder::new(vec![], level);
+                encoder.write_all(body)?;
+                encoder.finish()
+            }
+            Encoding::Deflate => {
+                let mut encoder = ZlibEncoder::new(vec![], level);
+                encoder.write_all(body)?;
+                encoder.finish()
+            }
+        }
+    }
+}
+
+/// The content coding, in the order of preference.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Encoding {
+    #[cfg(feature = "brotli")]
+    Brotli,
+    Gzip,
+    Deflate,
+}
+
+impl Encoding {
+    const ALL: &'static [Encoding] = &[
+        #[cfg(feature = "brotli")]
+        Encoding::Brotli,
+        Encoding::Gzip,
+        Encoding::Deflate,
+    ];
+
+    fn from_name(name: &str) -> Option<Encoding> {
+        match &*name.to_ascii_lowercase() {
+            #[cfg(feature = "brotli")]
+            "br" => Some(Encoding::Brotli),
+            "gzip" | "x-gzip" => Some(Encoding::Gzip),
+            "deflate" => Some(Encoding::Deflate),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            #[cfg(feature = "brotli")]
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use flate2::read::GzDecoder;
+    use http::header::{HeaderMap, HeaderValue, ACCEPT_ENCODING};
+    use std::io::Read;
+
+    use super::{Compression, Encoding};
+
+    fn negotiate(accept_encoding: &str) -> Option<Encoding> {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            ACCEPT_ENCODING,
+            HeaderValue::from_str(accept_encoding).unwrap(),
+        );
+        Compression::new().min_size(0).negotiate(&headers, 0)
+    }
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(negotiate("gzip, deflate"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("gzip;q=0.5, deflate"), Some(Encoding::Deflate));
+        assert_eq!(negotiate("gzip;q=0, identity"), None);
+        assert_eq!(negotiate("*"), Some(Encoding::ALL[0]));
+        assert_eq!(negotiate("gzip;q=0, br;q=0, *"), Some(Encoding::Deflate));
+        assert_eq!(
+            negotiate("br;q=0.5, deflate;q=0.5, *;q=0.8"),
+            Some(Encoding::Gzip)
+        );
+        assert_eq!(negotiate("*;q=0"), None);
+        assert_eq!(negotiate("identity"), None);
+        assert_eq!(Compression::new().negotiate(&HeaderMap::new(), 4096), None);
+    }
+
+    #[test]
+    fn test_min_size() {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT_ENCODING, HeaderValue::from_static("gzip"));
+        let compression = Compression::new().min_size(100);
+        assert_eq!(compression.negotiate(&headers, 99), None);
+        assert_eq!(compression.negotiate(&headers, 100), Some(Encoding::Gzip));
+    }
+
+    #[test]
+    fn test_encode_gzip() {
+        let body = br#"{"data":{"hero":{"name":"R2-D2"}}}"#;
+        let encoded = Compression::new().encode(Encoding::Gzip, body).unwrap();
+        let mut decoded = vec![];
+        GzDecoder::new(&encoded[..])
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, &body[..]);
+    }
+}
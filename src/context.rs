@@ -0,0 +1,519 @@
+//! Helpers for constructing GraphQL contexts from HTTP headers.
+//!
+//! Each helper is an endpoint which extracts a value from the request, and they can be
+//! combined with `and` in order to build the endpoint passed to the executors:
+//!
+//! * [`bearer_token`] - the token in the `Authorization: Bearer <token>` header
+//! * [`cookie`] - the value of the specified cookie
+//! * [`client_ip`] - the address of the client reported by the reverse proxy
+//! * [`header`] - the value of an arbitrary header
+//!
+//! The extracted values are optional.  By calling `required`, the endpoint instead fails
+//! with [`Unauthorized`] if the value is missing, which the executors report as
+//! a GraphQL response with the status `401 Unauthorized`:
+//!
+//! ```json
+//! {
+//!   "data": null,
+//!   "errors": [{
+//!     "message": "missing bearer token",
+//!     "extensions": { "code": "UNAUTHENTICATED" }
+//!   }]
+//! }
+//! ```
+//!
+//! # Example
+//!
+//! ```
+//! # #[macro_use]
+//! # extern crate finchers;
+//! # extern crate finchers_juniper;
+//! # #[macro_use]
+//! # extern crate juniper;
+//! # use finchers::prelude::*;
+//! # use finchers_juniper::context::{bearer_token, client_ip, header};
+//! # use juniper::{EmptyMutation, RootNode};
+//! # use std::net::IpAddr;
+//! struct MyContext {
+//!     token: String,
+//!     client_ip: Option<IpAddr>,
+//!     user_agent: Option<String>,
+//! }
+//! impl juniper::Context for MyContext {}
+//! # struct Query;
+//! # graphql_object!(Query: MyContext |&self| {
+//! #     field apiVersion() -> &str { "1.0" }
+//! # });
+//!
+//! # fn main() {
+//! # let schema = RootNode::new(Query, EmptyMutation::<MyContext>::new());
+//! let fetch_graphql_context = bearer_token()
+//!     .required()
+//!     .and(client_ip())
+//!     .and(header("user-agent"))
+//!     .map(|token, client_ip, user_agent| MyContext {
+//!         token,
+//!         client_ip,
+//!         user_agent,
+//!     });
+//!
+//! let endpoint = path!(/ "graphql" /)
+//!     .and(fetch_graphql_context)
+//!     .wrap(finchers_juniper::execute::nonblocking(schema));
+//! # drop(endpoint);
+//! # }
+//! ```
+//!
+//! [`bearer_token`]: ./fn.bearer_token.html
+//! [`cookie`]: ./fn.cookie.html
+//! [`client_ip`]: ./fn.client_ip.html
+//! [`header`]: ./fn.header.html
+//! [`Unauthorized`]: ./struct.Unauthorized.html
+
+use finchers::endpoint::{ApplyContext, ApplyResult, Endpoint};
+use finchers::error::{Error, HttpError};
+use finchers::input::Input;
+
+use futures::future;
+use futures::{Future, Poll};
+
+use http::header::{HeaderName, HeaderValue};
+use http::{header, HeaderMap, StatusCode};
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+
+use request::GraphQLResponse;
+
+// ==== Unauthorized ====
+
+/// An error which indicates that the credentials required for the request are missing
+/// or invalid.
+///
+/// The executors report this error as a GraphQL response with the status `401 Unauthorized`
+/// and the error code `UNAUTHENTICATED`, when it is returned from the endpoint which
+/// constructs the context.
+#[derive(Debug, Clone)]
+pub struct Unauthorized {
+    message: String,
+    challenge: Option<String>,
+}
+
+impl Unauthorized {
+    /// Creates a new `Unauthorized` with the specified message.
+    pub fn new(message: impl Into<String>) -> Unauthorized {
+        Unauthorized {
+            message: message.into(),
+            challenge: None,
+        }
+    }
+
+    /// Sets the challenge sent in the `WWW-Authenticate` header, e.g. `Bearer`.
+    pub fn challenge(self, challenge: impl Into<String>) -> Unauthorized {
+        Unauthorized {
+            challenge: Some(challenge.into()),
+            ..self
+        }
+    }
+
+    /// Returns the message of this error.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub(crate) fn to_response(&self) -> GraphQLResponse {
+        let mut response =
+            GraphQLResponse::error(StatusCode::UNAUTHORIZED, &self.message, "UNAUTHENTICATED");
+        self.headers(response.headers_mut());
+        response
+    }
+}
+
+impl fmt::Display for Unauthorized {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl HttpError for Unauthorized {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::UNAUTHORIZED
+    }
+
+    fn headers(&self, headers: &mut HeaderMap) {
+        if let Some(value) = self
+            .challenge
+            .as_ref()
+            .and_then(|challenge| HeaderValue::from_str(challenge).ok())
+        {
+            headers.insert(header::WWW_AUTHENTICATE, value);
+        }
+    }
+}
+
+// ==== Required ====
+
+/// An endpoint which fails with `Unauthorized` if the value extracted by the inner endpoint
+/// is missing.
+#[derive(Debug)]
+pub struct Required<E> {
+    endpoint: E,
+    error: Unauthorized,
+}
+
+impl<'a, E, T> Endpoint<'a> for Required<E>
+where
+    E: Endpoint<'a, Output = (Option<T>,)>,
+{
+    type Output = (T,);
+    type Future = RequiredFuture<E::Future>;
+
+    fn apply(&'a self, cx: &mut ApplyContext<'_>) -> ApplyResult<Self::Future> {
+        Ok(RequiredFuture {
+            future: self.endpoint.apply(cx)?,
+            error: Some(self.error.clone()),
+        })
+    }
+}
+
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct RequiredFuture<F> {
+    future: F,
+    error: Option<Unauthorized>,
+}
+
+impl<F, T> Future for RequiredFuture<F>
+where
+    F: Future<Item = (Option<T>,), Error = Error>,
+{
+    type Item = (T,);
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match try_ready!(self.future.poll()) {
+            (Some(value),) => Ok((value,).into()),
+            (None,) => Err(self
+                .error
+                .take()
+                .expect("the future has already polled")
+                .into()),
+        }
+    }
+}
+
+// ==== BearerToken ====
+
+/// Creates an endpoint which extracts the token from the `Authorization` header
+/// with the `Bearer` scheme.
+pub fn bearer_token() -> BearerToken {
+    BearerToken { _priv: () }
+}
+
+#[allow(missing_docs)]
+#[derive(Debug)]
+pub struct BearerToken {
+    _priv: (),
+}
+
+impl BearerToken {
+    /// Makes the endpoint fail with `Unauthorized` if the token is missing.
+    pub fn required(self) -> Required<BearerToken> {
+        Required {
+            endpoint: self,
+            error: Unauthorized::new("missing bearer token").challenge("Bearer"),
+        }
+    }
+}
+
+impl<'a> Endpoint<'a> for BearerToken {
+    type Output = (Option<String>,);
+    type Future = future::FutureResult<Self::Output, Error>;
+
+    fn apply(&'a self, cx: &mut ApplyContext<'_>) -> ApplyResult<Self::Future> {
+        let token = cx
+            .input()
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_bearer_token)
+            .map(ToOwned::to_owned);
+        Ok(future::ok((token,)))
+    }
+}
+
+fn parse_bearer_token(value: &str) -> Option<&str> {
+    let mut parts = value.trim().splitn(2, ' ');
+    let scheme = parts.next()?;
+    let token = parts.next()?.trim();
+    if scheme.eq_ignore_ascii_case("bearer") && !token.is_empty() {
+        Some(token)
+    } else {
+        None
+    }
+}
+
+// ==== CookieValue ====
+
+/// Creates an endpoint which extracts the value of the cookie with the specified name.
+pub fn cookie(name: impl Into<String>) -> CookieValue {
+    CookieValue { name: name.into() }
+}
+
+#[allow(missing_docs)]
+#[derive(Debug)]
+pub struct CookieValue {
+    name: String,
+}
+
+impl CookieValue {
+    /// Makes the endpoint fail with `Unauthorized` if the cookie is missing.
+    pub fn required(self) -> Required<CookieValue> {
+        let error = Unauthorized::new(format!("missing cookie `{}`", self.name));
+        Required {
+            endpoint: self,
+            error,
+        }
+    }
+}
+
+impl<'a> Endpoint<'a> for CookieValue {
+    type Output = (Option<String>,);
+    type Future = future::FutureResult<Self::Output, Error>;
+
+    fn apply(&'a self, cx: &mut ApplyContext<'_>) -> ApplyResult<Self::Future> {
+        let value = cx
+            .input()
+            .headers()
+            .get_all(header::COOKIE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(';'))
+            .filter_map(|pair| {
+                let mut pair = pair.splitn(2, '=');
+                match (pair.next(), pair.next()) {
+                    (Some(name), Some(value)) if name.trim() == self.name => {
+                        Some(value.trim().trim_matches('"').to_owned())
+                    }
+                    _ => None,
+                }
+            })
+            .next();
+        Ok(future::ok((value,)))
+    }
+}
+
+// ==== ClientIp ====
+
+/// Creates an endpoint which extracts the IP address of the client.
+///
+/// The address is taken from the `Forwarded`, `X-Forwarded-For` or `X-Real-IP` header,
+/// in this order.  Since these headers can be set arbitrarily by the clients, this endpoint
+/// should be used only behind a reverse proxy which overwrites them.
+pub fn client_ip() -> ClientIp {
+    ClientIp { _priv: () }
+}
+
+#[allow(missing_docs)]
+#[derive(Debug)]
+pub struct ClientIp {
+    _priv: (),
+}
+
+impl<'a> Endpoint<'a> for ClientIp {
+    type Output = (Option<IpAddr>,);
+    type Future = future::FutureResult<Self::Output, Error>;
+
+    fn apply(&'a self, cx: &mut ApplyContext<'_>) -> ApplyResult<Self::Future> {
+        Ok(future::ok((parse_client_ip(cx.input()),)))
+    }
+}
+
+fn parse_client_ip(input: &Input) -> Option<IpAddr> {
+    let headers = input.headers();
+    let first_value = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(',').next())
+            .map(|value| value.trim())
+    };
+
+    let forwarded = first_value("forwarded").and_then(|element| {
+        element
+            .split(';')
+            .filter_map(|pair| {
+                let mut pair = pair.splitn(2, '=');
+                match (pair.next(), pair.next()) {
+                    (Some(name), Some(value)) if name.trim().eq_ignore_ascii_case("for") => {
+                        Some(value.trim().trim_matches('"'))
+                    }
+                    _ => None,
+                }
+            })
+            .next()
+    });
+
+    forwarded
+        .into_iter()
+        .chain(first_value("x-forwarded-for"))
+        .chain(first_value("x-real-ip"))
+        .filter_map(parse_node)
+        .next()
+}
+
+// Parses the node in the forms of `192.0.2.1`, `192.0.2.1:8080`, `2001:db8::1`
+// or `[2001:db8::1]:8080`.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    node.parse()
+        .ok()
+        .or_else(|| node.parse::<SocketAddr>().ok().map(|addr| addr.ip()))
+        .or_else(|| {
+            if node.starts_with('[') && node.ends_with(']') {
+                node[1..node.len() - 1].parse().ok()
+            } else {
+                None
+            }
+        })
+}
+
+// ==== Header ====
+
+/// Creates an endpoint which extracts the value of the specified header as a string.
+///
+/// # Panics
+///
+/// This function panics if the specified header name is invalid.
+pub fn header(name: &str) -> Header {
+    Header {
+        name: HeaderName::from_bytes(name.as_bytes()).expect("invalid header name"),
+    }
+}
+
+#[allow(missing_docs)]
+#[derive(Debug)]
+pub struct Header {
+    name: HeaderName,
+}
+
+impl Header {
+    /// Makes the endpoint fail with `Unauthorized` if the header is missing.
+    pub fn required(self) -> Required<Header> {
+        let error = Unauthorized::new(format!("missing header `{}`", self.name));
+        Required {
+            endpoint: self,
+            error,
+        }
+    }
+}
+
+impl<'a> Endpoint<'a> for Header {
+    type Output = (Option<String>,);
+    type Future = future::FutureResult<Self::Output, Error>;
+
+    fn apply(&'a self, cx: &mut ApplyContext<'_>) -> ApplyResult<Self::Future> {
+        let value = cx
+            .input()
+            .headers()
+            .get(&self.name)
+            .and_then(|value| value.to_str().ok())
+            .map(ToOwned::to_owned);
+        Ok(future::ok((value,)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use finchers::prelude::*;
+    use finchers::test;
+    use http::{Request, StatusCode};
+    use juniper::tests::model::Database;
+    use serde_json;
+    use std::net::IpAddr;
+
+    use super::{bearer_token, client_ip, cookie, header, parse_node};
+    use execute::current_thread;
+    use testing::{database_schema, json_body};
+
+    #[test]
+    fn test_extractors() {
+        let mut runner = test::runner(
+            bearer_token()
+                .and(cookie("session"))
+                .and(client_ip())
+                .and(header("x-api-key"))
+                .map(|token, session, client_ip, api_key| (token, session, client_ip, api_key)),
+        );
+        let output = runner
+            .apply(
+                Request::get("/")
+                    .header("authorization", "Bearer secret")
+                    .header("cookie", "theme=dark; session=abc")
+                    .header("x-forwarded-for", "192.0.2.1, 10.0.0.1")
+                    .header("x-api-key", "key"),
+            )
+            .unwrap();
+        assert_eq!(
+            output,
+            (
+                Some("secret".to_owned()),
+                Some("abc".to_owned()),
+                Some("192.0.2.1".parse::<IpAddr>().unwrap()),
+                Some("key".to_owned()),
+            )
+        );
+
+        let output = runner
+            .apply(Request::get("/").header("authorization", "Basic dXNlcg=="))
+            .unwrap();
+        assert_eq!(output, (None, None, None, None));
+    }
+
+    #[test]
+    fn test_client_ip() {
+        let mut runner = test::runner(client_ip());
+        let output = runner
+            .apply(
+                Request::get("/")
+                    .header("forwarded", "for=\"[2001:db8::1]:8080\";proto=https")
+                    .header("x-real-ip", "192.0.2.1"),
+            )
+            .unwrap();
+        assert_eq!(output, Some("2001:db8::1".parse().unwrap()));
+
+        assert_eq!(parse_node("192.0.2.1:8080"), "192.0.2.1".parse().ok());
+        assert_eq!(parse_node("unknown"), None);
+    }
+
+    #[test]
+    fn test_required_bearer_token() {
+        let schema = database_schema();
+        let endpoint = bearer_token()
+            .required()
+            .map(|_: String| Database::new())
+            .wrap(current_thread(schema));
+        let mut runner = test::runner(endpoint);
+
+        let response = runner
+            .perform(Request::get("/?query={hero{name}}").header("authorization", "Bearer x"))
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = runner.perform("/?query={hero{name}}").unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            response.headers().get("www-authenticate").unwrap(),
+            "Bearer"
+        );
+        let body = json_body(&response);
+        assert_eq!(body["data"], serde_json::Value::Null);
+        assert_eq!(body["errors"][0]["message"], "missing bearer token");
+        assert_eq!(body["errors"][0]["extensions"]["code"], "UNAUTHENTICATED");
+
+        // the method is checked before the credentials.
+        let response = runner
+            .perform(Request::put("/?query={hero{name}}"))
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(response.headers()["allow"], "GET, HEAD, POST");
+    }
+}
@@ -0,0 +1,527 @@
+//! Cross-origin resource sharing (CORS) for the GraphQL endpoints.
+//!
+//! The wrapper [`Cors`] answers the preflight requests by itself, without applying the
+//! wrapped endpoint, and adds the `Access-Control-*` headers to the responses for
+//! the requests from the allowed origins.  Since every preflight request is answered,
+//! the wrapper should be applied to the endpoint after matching the path.
+//!
+//! # Example
+//!
+//! ```
+//! # #[macro_use]
+//! # extern crate finchers;
+//! # extern crate finchers_juniper;
+//! # extern crate juniper;
+//! # use finchers::prelude::*;
+//! # use finchers_juniper::cors::Cors;
+//! # use juniper::tests::model::Database;
+//! # use juniper::{EmptyMutation, RootNode};
+//! # fn main() {
+//! # let schema = RootNode::new(Database::new(), EmptyMutation::<Database>::new());
+//! let cors = Cors::new()
+//!     .allow_origin("https://example.com")
+//!     .allow_credentials(true);
+//!
+//! let endpoint = path!(/ "graphql" /).and(
+//!     endpoint::unit()
+//!         .map(Database::new)
+//!         .wrap(finchers_juniper::execute::nonblocking(schema))
+//!         .wrap(cors),
+//! );
+//! # drop(endpoint);
+//! # }
+//! ```
+//!
+//! [`Cors`]: ./struct.Cors.html
+
+use finchers::endpoint::wrapper::Wrapper;
+use finchers::endpoint::{with_get_cx, ApplyContext, ApplyResult, Endpoint};
+use finchers::error::{Error, HttpError};
+use finchers::input::Input;
+use finchers::output::{Output, OutputContext};
+
+use futures::{Async, Future, Poll};
+
+use http::header::{self, HeaderMap, HeaderName, HeaderValue};
+use http::{Method, Response, StatusCode};
+use std::time::Duration;
+
+use request::GraphQLResponse;
+
+/// The configuration of CORS.
+///
+/// By default, the requests from any origin are allowed with the methods `GET` and `POST`,
+/// and with the headers used by GraphQL clients, i.e. `Content-Type`, `Authorization`,
+/// `Apollographql-Client-Name`, `Apollographql-Client-Version`, `X-Apollo-Tracing`,
+/// `X-GraphQL-CSRF`, `Apollo-Require-Preflight` and `X-Request-Id`.
+/// The header `X-Request-Id` in the responses is exposed to the clients.
+#[derive(Debug, Clone)]
+pub struct Cors {
+    origins: Option<Vec<String>>,
+    methods: Vec<Method>,
+    headers: Vec<HeaderName>,
+    expose_headers: Vec<HeaderName>,
+    credentials: bool,
+    max_age: Option<Duration>,
+}
+
+impl Default for Cors {
+    fn default() -> Cors {
+        Cors {
+            origins: None,
+            methods: vec![Method::GET, Method::POST],
+            headers: [
+                "content-type",
+                "authorization",
+                "apollographql-client-name",
+                "apollographql-client-version",
+                "x-apollo-tracing",
+                "x-graphql-csrf",
+                "apollo-require-preflight",
+                "x-request-id",
+            ]
+            .iter()
+            .map(|&name| HeaderName::from_static(name))
+            .collect(),
+            expose_headers: vec![HeaderName::from_static("x-request-id")],
+            credentials: false,
+            max_age: None,
+        }
+    }
+}
+
+impl Cors {
+    /// Creates a new `Cors` with the default values.
+    pub fn new() -> Cors {
+        Cors::default()
+    }
+
+    /// Allows the requests from the specified origin, e.g. `https://example.com`.
+    ///
+    /// Once this method is called, only the requests from the specified origins are allowed.
+    pub fn allow_origin(mut self, origin: impl Into<String>) -> Cors {
+        self.origins
+            .get_or_insert_with(Vec::new)
+            .push(origin.into());
+        self
+    }
+
+    /// Allows the specified method in addition to the default ones.
+    pub fn allow_method(mut self, method: Method) -> Cors {
+        if !self.methods.contains(&method) {
+            self.methods.push(method);
+        }
+        self
+    }
+
+    /// Allows the specified request header in addition to the default ones.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the specified header name is invalid.
+    pub fn allow_header(mut self, name: &str) -> Cors {
+        let name = HeaderName::from_bytes(name.as_bytes()).expect("invalid header name");
+        if !self.headers.contains(&name) {
+            self.headers.push(name);
+        }
+        self
+    }
+
+    /// Exposes the specified response header to the clients in addition to the default ones.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the specified header name is invalid.
+    pub fn expose_header(mut self, name: &str) -> Cors {
+        let name = HeaderName::from_bytes(name.as_bytes()).expect("invalid header name");
+        if !self.expose_headers.contains(&name) {
+            self.expose_headers.push(name);
+        }
+        self
+    }
+
+    /// Specifies whether to allow the requests with credentials, such as cookies.
+    ///
+    /// The credentials are allowed only for the origins specified by [`allow_origin`].
+    /// If no origin is specified, `Access-Control-Allow-Credentials` is never sent,
+    /// since the responses with the wildcard origin are readable from any site.
+    ///
+    /// [`allow_origin`]: #method.allow_origin
+    pub fn allow_credentials(self, credentials: bool) -> Cors {
+        Cors {
+            credentials,
+            ..self
+        }
+    }
+
+    /// Sets the duration for which the results of preflight requests can be cached.
+    pub fn max_age(self, max_age: Duration) -> Cors {
+        Cors {
+            max_age: Some(max_age),
+            ..self
+        }
+    }
+
+    // Returns the value of `Access-Control-Allow-Origin`, if the origin is allowed.
+    fn allowed_origin(&self, headers: &HeaderMap) -> Option<HeaderValue> {
+        let origin = headers.get(header::ORIGIN)?;
+        match self.origins {
+            None => Some(HeaderValue::from_static("*")),
+            Some(ref origins) => {
+                if origins.iter().any(|allowed| origin == allowed.as_str()) {
+                    Some(origin.clone())
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    fn is_preflight_allowed(&self, headers: &HeaderMap) -> bool {
+        let is_method_allowed = headers
+            .get(header::ACCESS_CONTROL_REQUEST_METHOD)
+            .and_then(|value| Method::from_bytes(value.as_bytes()).ok())
+            .map_or(false, |method| self.methods.contains(&method));
+        let are_headers_allowed = headers
+            .get_all(header::ACCESS_CONTROL_REQUEST_HEADERS)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(|name| name.trim())
+            .filter(|name| !name.is_empty())
+            .all(|name| {
+                self.headers
+                    .iter()
+                    .any(|allowed| name.eq_ignore_ascii_case(allowed.as_str()))
+            });
+        is_method_allowed && are_headers_allowed
+    }
+
+    // Adds the headers to the response for the request from the allowed origin,
+    // and returns `true` if the response varies by the origin.
+    fn add_headers(&self, input: &mut Input, is_preflight: bool) -> bool {
+        let origin = match self.allowed_origin(input.headers()) {
+            Some(origin) => origin,
+            None => return self.origins.is_some(),
+        };
+        if is_preflight && !self.is_preflight_allowed(input.headers()) {
+            return origin != "*";
+        }
+
+        let is_wildcard = origin == "*";
+        let headers = input.response_headers();
+        headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+        if self.credentials && !is_wildcard {
+            headers.insert(
+                header::ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                HeaderValue::from_static("true"),
+            );
+        }
+        if is_preflight {
+            headers.insert(
+                header::ACCESS_CONTROL_ALLOW_METHODS,
+                join(self.methods.iter().map(Method::as_str)),
+            );
+            headers.insert(
+                header::ACCESS_CONTROL_ALLOW_HEADERS,
+                join(self.headers.iter().map(HeaderName::as_str)),
+            );
+            if let Some(max_age) = self.max_age {
+                headers.insert(
+                    header::ACCESS_CONTROL_MAX_AGE,
+                    HeaderValue::from_str(&max_age.as_secs().to_string())
+                        .expect("should be a valid header value"),
+                );
+            }
+        } else if !self.expose_headers.is_empty() {
+            headers.insert(
+                header::ACCESS_CONTROL_EXPOSE_HEADERS,
+                join(self.expose_headers.iter().map(HeaderName::as_str)),
+            );
+        }
+        !is_wildcard
+    }
+}
+
+// Adds `Vary: origin` to the error responses, which are rendered without `CorsResponse`.
+fn add_vary(input: &mut Input) {
+    input
+        .response_headers()
+        .append(header::VARY, HeaderValue::from_static("origin"));
+}
+
+fn join<'a>(values: impl Iterator<Item = &'a str>) -> HeaderValue {
+    HeaderValue::from_str(&values.collect::<Vec<_>>().join(", "))
+        .expect("should be a valid header value")
+}
+
+impl<'a, E> Wrapper<'a, E> for Cors
+where
+    E: Endpoint<'a, Output = (GraphQLResponse,)>,
+{
+    type Output = (CorsResponse,);
+    type Endpoint = CorsEndpoint<E>;
+
+    fn wrap(self, endpoint: E) -> Self::Endpoint {
+        CorsEndpoint {
+            endpoint,
+            cors: self,
+        }
+    }
+}
+
+#[allow(missing_docs)]
+#[derive(Debug)]
+pub struct CorsEndpoint<E> {
+    endpoint: E,
+    cors: Cors,
+}
+
+impl<'a, E> Endpoint<'a> for CorsEndpoint<E>
+where
+    E: Endpoint<'a, Output = (GraphQLResponse,)>,
+{
+    type Output = (CorsResponse,);
+    type Future = CorsFuture<E::Future>;
+
+    fn apply(&'a self, cx: &mut ApplyContext<'_>) -> ApplyResult<Self::Future> {
+        let is_preflight = *cx.input().method() == Method::OPTIONS
+            && cx
+                .input()
+                .headers()
+                .contains_key(header::ACCESS_CONTROL_REQUEST_METHOD);
+        let vary = self.cors.add_headers(cx.input(), is_preflight);
+        if is_preflight {
+            // The preflight responses always depend on the origin.
+            return Ok(CorsFuture {
+                future: None,
+                vary: true,
+            });
+        }
+        match self.endpoint.apply(cx) {
+            Ok(future) => Ok(CorsFuture {
+                future: Some(future),
+                vary,
+            }),
+            Err(err) => {
+                // `404` means that the request is not routed to this endpoint.
+                if vary && err.status_code() != StatusCode::NOT_FOUND {
+                    add_vary(cx.input());
+                }
+                Err(err)
+            }
+        }
+    }
+}
+
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct CorsFuture<F> {
+    future: Option<F>,
+    vary: bool,
+}
+
+impl<F> Future for CorsFuture<F>
+where
+    F: Future<Item = (GraphQLResponse,), Error = Error>,
+{
+    type Item = (CorsResponse,);
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let response = match self.future {
+            Some(ref mut future) => {
+                let (mut response,) = match future.poll() {
+                    Ok(Async::Ready(output)) => output,
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Err(err) => {
+                        if self.vary {
+                            with_get_cx(|input| add_vary(input));
+                        }
+                        return Err(err);
+                    }
+                };
+                if self.vary {
+                    // Appended to the response since the header may have other values.
+                    response
+                        .headers_mut()
+                        .append(header::VARY, HeaderValue::from_static("origin"));
+                }
+                Some(response)
+            }
+            None => None,
+        };
+        Ok((CorsResponse {
+            response,
+            vary: self.vary,
+        },)
+            .into())
+    }
+}
+
+/// The response from the endpoint wrapped by `Cors`.
+///
+/// It is either the GraphQL response, or the empty response to a preflight request.
+#[derive(Debug)]
+pub struct CorsResponse {
+    response: Option<GraphQLResponse>,
+    vary: bool,
+}
+
+impl CorsResponse {
+    /// Returns `true` if this is the response to a preflight request.
+    pub fn is_preflight(&self) -> bool {
+        self.response.is_none()
+    }
+
+    /// Returns the GraphQL response, if this is not the response to a preflight request.
+    pub fn into_graphql_response(self) -> Option<GraphQLResponse> {
+        self.response
+    }
+}
+
+impl Output for CorsResponse {
+    type Body = Vec<u8>;
+    type Error = Error;
+
+    fn respond(self, cx: &mut OutputContext<'_>) -> Result<Response<Self::Body>, Self::Error> {
+        match self.response {
+            Some(response) => response.respond(cx),
+            None => {
+                let mut response = Response::builder();
+                response.status(StatusCode::NO_CONTENT);
+                if self.vary {
+                    response.header(header::VARY, "origin");
+                }
+                Ok(response.body(vec![]).expect("should be a valid response"))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use finchers::prelude::*;
+    use finchers::test;
+    use http::{Request, StatusCode};
+    use juniper::tests::model::Database;
+    use std::time::Duration;
+
+    use super::Cors;
+    use context::bearer_token;
+    use execute::current_thread;
+    use testing::{database, database_schema};
+
+    #[test]
+    fn test_cors() {
+        let schema = database_schema();
+        let endpoint = bearer_token()
+            .required()
+            .map(|_: String| Database::new())
+            .wrap(current_thread(schema).compression(Default::default()))
+            .wrap(
+                Cors::new()
+                    .allow_origin("https://example.com")
+                    .max_age(Duration::from_secs(600)),
+            );
+        let mut runner = test::runner(endpoint);
+
+        // The preflight request is answered without the credentials.
+        let response = runner
+            .perform(
+                Request::options("/")
+                    .header("origin", "https://example.com")
+                    .header("access-control-request-method", "POST")
+                    .header(
+                        "access-control-request-headers",
+                        "Content-Type, Authorization",
+                    ),
+            )
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let headers = response.headers();
+        assert_eq!(
+            headers["access-control-allow-origin"],
+            "https://example.com"
+        );
+        assert_eq!(headers["access-control-allow-methods"], "GET, POST");
+        assert!(headers["access-control-allow-headers"]
+            .to_str()
+            .unwrap()
+            .contains("authorization"));
+        assert_eq!(headers["access-control-max-age"], "600");
+        assert_eq!(headers["vary"], "origin");
+
+        let response = runner
+            .perform(
+                Request::options("/")
+                    .header("origin", "https://example.com")
+                    .header("access-control-request-method", "PUT"),
+            )
+            .unwrap();
+        assert!(!response
+            .headers()
+            .contains_key("access-control-allow-origin"));
+
+        let response = runner
+            .perform(
+                Request::get("/?query={hero{name}}")
+                    .header("origin", "https://example.com")
+                    .header("authorization", "Bearer x"),
+            )
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let headers = response.headers();
+        assert_eq!(
+            headers["access-control-allow-origin"],
+            "https://example.com"
+        );
+        assert_eq!(headers["access-control-expose-headers"], "x-request-id");
+        let vary: Vec<_> = headers.get_all("vary").iter().collect();
+        assert_eq!(vary, vec!["origin", "accept-encoding"]);
+
+        let response = runner
+            .perform(
+                Request::get("/?query={hero{name}}")
+                    .header("origin", "https://evil.example.com")
+                    .header("authorization", "Bearer x"),
+            )
+            .unwrap();
+        assert!(!response
+            .headers()
+            .contains_key("access-control-allow-origin"));
+    }
+
+    #[test]
+    fn test_cors_credentials_without_origins() {
+        let schema = database_schema();
+        let endpoint = database()
+            .wrap(current_thread(schema))
+            .wrap(Cors::new().allow_credentials(true));
+        let mut runner = test::runner(endpoint);
+
+        let response = runner
+            .perform(
+                Request::get("/?query={hero{name}}").header("origin", "https://evil.example.com"),
+            )
+            .unwrap();
+        let headers = response.headers();
+        assert_eq!(headers["access-control-allow-origin"], "*");
+        assert!(!headers.contains_key("access-control-allow-credentials"));
+    }
+
+    #[test]
+    fn test_cors_vary_on_errors() {
+        let schema = database_schema();
+        let endpoint = database()
+            .wrap(current_thread(schema))
+            .wrap(Cors::new().allow_origin("https://example.com"));
+        let mut runner = test::runner(endpoint);
+
+        let response = runner
+            .perform(Request::get("/").header("origin", "https://example.com"))
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(response.headers()["vary"], "origin");
+    }
+}
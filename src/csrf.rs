@@ -0,0 +1,108 @@
+//! Prevention of cross-site request forgery.
+
+use http::header::{self, HeaderMap, HeaderName};
+
+/// The configuration of the prevention of cross-site request forgery (CSRF).
+///
+/// Browsers send the `GET` requests and the `POST` requests with a "simple" content type
+/// (`application/x-www-form-urlencoded`, `multipart/form-data` or `text/plain`) to other
+/// origins without a CORS preflight, so the GraphQL operations could be executed on behalf
+/// of the user by a malicious site.  When this prevention is enabled, the requests are
+/// accepted only if they specify a content type which is not simple, such as
+/// `application/json`, or contain a non-empty value of one of the configured headers.
+/// The other requests are rejected with `400 Bad Request`.
+///
+/// By default, the headers `X-GraphQL-CSRF` and `Apollo-Require-Preflight` are accepted.
+#[derive(Debug, Clone)]
+pub struct CsrfPrevention {
+    headers: Vec<HeaderName>,
+}
+
+impl Default for CsrfPrevention {
+    fn default() -> CsrfPrevention {
+        CsrfPrevention {
+            headers: vec![
+                HeaderName::from_static("x-graphql-csrf"),
+                HeaderName::from_static("apollo-require-preflight"),
+            ],
+        }
+    }
+}
+
+impl CsrfPrevention {
+    /// Creates a new `CsrfPrevention` with the default values.
+    pub fn new() -> CsrfPrevention {
+        CsrfPrevention::default()
+    }
+
+    /// Adds a header which marks the request as preflighted.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the specified header name is invalid.
+    pub fn header(mut self, name: &str) -> CsrfPrevention {
+        let name = HeaderName::from_bytes(name.as_bytes()).expect("invalid header name");
+        if !self.headers.contains(&name) {
+            self.headers.push(name);
+        }
+        self
+    }
+
+    /// Returns `true` if a browser would have sent a preflight request before this request.
+    pub(crate) fn is_preflighted(&self, headers: &HeaderMap) -> bool {
+        let has_non_simple_content_type = headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map_or(false, |value| {
+                let essence = value.split(';').next().unwrap_or("").trim();
+                ![
+                    "application/x-www-form-urlencoded",
+                    "multipart/form-data",
+                    "text/plain",
+                ]
+                .iter()
+                .any(|simple| essence.eq_ignore_ascii_case(simple))
+            });
+        has_non_simple_content_type
+            || self.headers.iter().any(|name| {
+                headers
+                    .get(name)
+                    .map_or(false, |value| !value.as_bytes().is_empty())
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http::header::HeaderMap;
+
+    use super::CsrfPrevention;
+
+    fn is_preflighted(csrf_prevention: &CsrfPrevention, headers: &[(&'static str, &str)]) -> bool {
+        let mut map = HeaderMap::new();
+        for &(name, value) in headers {
+            map.insert(name, value.parse().unwrap());
+        }
+        csrf_prevention.is_preflighted(&map)
+    }
+
+    #[test]
+    fn test_is_preflighted() {
+        let csrf_prevention = CsrfPrevention::new().header("x-requested-with");
+        assert!(!is_preflighted(&csrf_prevention, &[]));
+        assert!(!is_preflighted(
+            &csrf_prevention,
+            &[("content-type", "text/plain; charset=utf-8")]
+        ));
+        assert!(!is_preflighted(&csrf_prevention, &[("x-graphql-csrf", "")]));
+        assert!(is_preflighted(
+            &csrf_prevention,
+            &[("content-type", "application/json")]
+        ));
+        assert!(is_preflighted(&csrf_prevention, &[("x-graphql-csrf", "1")]));
+        assert!(is_preflighted(
+            &csrf_prevention,
+            &[("x-requested-with", "XMLHttpRequest")]
+        ));
+    }
+}
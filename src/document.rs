@@ -0,0 +1,554 @@
+//! Lightweight inspection of GraphQL documents.
+//!
+//! Juniper does not expose the AST of parsed documents, so the definitions
+//! are extracted by scanning the tokens produced by its lexer.
+//! The validation of documents is still left to Juniper.
+
+use juniper::meta::{Field, MetaType};
+use juniper::parser::{Lexer, Token};
+use juniper::{GraphQLType, RootNode};
+use serde_json;
+use std::borrow::Cow;
+
+/// The type of a GraphQL operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum OperationKind {
+    Query,
+    Mutation,
+    Subscription,
+}
+
+/// The summary of definitions in a GraphQL document.
+#[derive(Debug)]
+pub(crate) struct Document {
+    operations: Vec<Operation>,
+    fragments: Vec<Fragment>,
+    normalized: String,
+}
+
+#[derive(Debug)]
+pub(crate) struct Operation {
+    kind: OperationKind,
+    name: Option<String>,
+    selection_set: Vec<Selection>,
+}
+
+impl Operation {
+    pub(crate) fn kind(&self) -> OperationKind {
+        self.kind
+    }
+
+    pub(crate) fn name(&self) -> Option<&str> {
+        self.name.as_ref().map(String::as_str)
+    }
+}
+
+#[derive(Debug)]
+struct Fragment {
+    name: String,
+    type_condition: String,
+    selection_set: Vec<Selection>,
+}
+
+// The arguments and directives are not retained.
+#[derive(Debug)]
+enum Selection {
+    Field {
+        name: String,
+        selection_set: Vec<Selection>,
+    },
+    FragmentSpread(String),
+    InlineFragment {
+        type_condition: Option<String>,
+        selection_set: Vec<Selection>,
+    },
+}
+
+impl Document {
+    /// Scans the specified source, returning `None` if it contains a lexical error
+    /// or a malformed definition.
+    pub(crate) fn parse(source: &str) -> Option<Document> {
+        let mut scanner = Scanner {
+            lexer: Lexer::new(source),
+            peeked: None,
+            normalized: String::with_capacity(source.len()),
+        };
+        let mut operations = vec![];
+        let mut fragments = vec![];
+        loop {
+            let kind = match scanner.next()? {
+                Token::EndOfFile => break,
+                Token::CurlyOpen => {
+                    // The query shorthand.
+                    operations.push(Operation {
+                        kind: OperationKind::Query,
+                        name: None,
+                        selection_set: scanner.selection_set()?,
+                    });
+                    continue;
+                }
+                Token::Name("query") => OperationKind::Query,
+                Token::Name("mutation") => OperationKind::Mutation,
+                Token::Name("subscription") => OperationKind::Subscription,
+                Token::Name("fragment") => {
+                    let name = scanner.name()?;
+                    match scanner.next()? {
+                        Token::Name("on") => {}
+                        _ => return None,
+                    }
+                    let type_condition = scanner.name()?;
+                    let token = scanner.next()?;
+                    scanner.skip_until_selection_set(token)?;
+                    fragments.push(Fragment {
+                        name,
+                        type_condition,
+                        selection_set: scanner.selection_set()?,
+                    });
+                    continue;
+                }
+                _ => return None,
+            };
+
+            let mut token = scanner.next()?;
+            let name = match token {
+                Token::Name(name) => {
+                    token = scanner.next()?;
+                    Some(name.to_owned())
+                }
+                _ => None,
+            };
+            scanner.skip_until_selection_set(token)?;
+            operations.push(Operation {
+                kind,
+                name,
+                selection_set: scanner.selection_set()?,
+            });
+        }
+        Some(Document {
+            operations,
+            fragments,
+            normalized: scanner.normalized,
+        })
+    }
+
+    /// Returns the source of this document without comments and redundant whitespaces,
+    /// in which the tokens are separated by a single space.
+    pub(crate) fn normalized(&self) -> &str {
+        &self.normalized
+    }
+
+    /// Returns the operation which will be executed with the specified operation name.
+    pub(crate) fn operation(&self, name: Option<&str>) -> Option<&Operation> {
+        match name {
+            Some(name) => self
+                .operations
+                .iter()
+                .find(|op| op.name.as_ref().map(String::as_str) == Some(name)),
+            None if self.operations.len() == 1 => self.operations.first(),
+            None => None,
+        }
+    }
+
+    /// Returns the names of the fields selected on the root type of the specified operation,
+    /// including the ones in the fragments.
+    pub(crate) fn root_fields<'d>(&'d self, operation: &'d Operation) -> Vec<&'d str> {
+        let mut fields = vec![];
+        self.collect_fields(&operation.selection_set, &mut vec![], &mut fields);
+        fields
+    }
+
+    fn collect_fields<'d>(
+        &'d self,
+        selection_set: &'d [Selection],
+        spreads: &mut Vec<&'d str>,
+        fields: &mut Vec<&'d str>,
+    ) {
+        for selection in selection_set {
+            match *selection {
+                Selection::Field { ref name, .. } => {
+                    if !fields.contains(&&**name) {
+                        fields.push(name);
+                    }
+                }
+                Selection::FragmentSpread(ref name) => {
+                    if spreads.contains(&&**name) {
+                        continue;
+                    }
+                    if let Some(fragment) = self.fragments.iter().find(|f| f.name == *name) {
+                        spreads.push(name);
+                        self.collect_fields(&fragment.selection_set, spreads, fields);
+                    }
+                }
+                Selection::InlineFragment {
+                    ref selection_set, ..
+                } => self.collect_fields(selection_set, spreads, fields),
+            }
+        }
+    }
+
+    /// Visits the fields selected in the specified operation, along with the type
+    /// which the field belongs to and the depth from the root type.
+    ///
+    /// The fields unknown to the schema are skipped, and each fragment is visited
+    /// at most once on each path.
+    pub(crate) fn visit_fields<QueryT, MutationT, F>(
+        &self,
+        operation: &Operation,
+        root_node: &RootNode<'static, QueryT, MutationT>,
+        mut f: F,
+    ) where
+        QueryT: GraphQLType,
+        MutationT: GraphQLType,
+        F: FnMut(&MetaType<'_>, &Field<'_>, usize),
+    {
+        let root_type = match operation.kind {
+            OperationKind::Query => Some(root_node.schema.concrete_query_type()),
+            OperationKind::Mutation => root_node.schema.concrete_mutation_type(),
+            OperationKind::Subscription => None,
+        };
+        if let Some(root_type) = root_type {
+            let mut visitor = Visitor {
+                document: self,
+                root_node,
+                fragments: vec![],
+                f: &mut f,
+            };
+            visitor.visit(&operation.selection_set, root_type, 0);
+        }
+    }
+}
+
+struct Visitor<'d, 'r, QueryT: GraphQLType, MutationT: GraphQLType, F> {
+    document: &'d Document,
+    root_node: &'r RootNode<'static, QueryT, MutationT>,
+    fragments: Vec<&'d str>,
+    f: &'d mut F,
+}
+
+impl<'d, 'r, QueryT, MutationT, F> Visitor<'d, 'r, QueryT, MutationT, F>
+where
+    QueryT: GraphQLType,
+    MutationT: GraphQLType,
+    F: FnMut(&MetaType<'_>, &Field<'_>, usize),
+{
+    fn visit(&mut self, selection_set: &'d [Selection], parent: &'r MetaType<'r>, depth: usize) {
+        let schema = &self.root_node.schema;
+        for selection in selection_set {
+            match *selection {
+                Selection::Field {
+                    ref name,
+                    ref selection_set,
+                } => {
+                    let field = match parent.field_by_name(name) {
+                        Some(field) => field,
+                        None => continue,
+                    };
+                    (self.f)(parent, field, depth);
+                    if let Some(field_type) =
+                        schema.concrete_type_by_name(field.field_type.innermost_name())
+                    {
+                        self.visit(selection_set, field_type, depth + 1);
+                    }
+                }
+                Selection::FragmentSpread(ref name) => {
+                    if self.fragments.contains(&&**name) {
+                        continue;
+                    }
+                    let fragment = match self.document.fragments.iter().find(|f| f.name == *name) {
+                        Some(fragment) => fragment,
+                        None => continue,
+                    };
+                    if let Some(fragment_type) =
+                        schema.concrete_type_by_name(&fragment.type_condition)
+                    {
+                        self.fragments.push(name);
+                        self.visit(&fragment.selection_set, fragment_type, depth);
+                        self.fragments.pop();
+                    }
+                }
+                Selection::InlineFragment {
+                    ref type_condition,
+                    ref selection_set,
+                } => {
+                    let fragment_type = match *type_condition {
+                        Some(ref name) => match schema.concrete_type_by_name(name) {
+                            Some(fragment_type) => fragment_type,
+                            None => continue,
+                        },
+                        None => parent,
+                    };
+                    self.visit(selection_set, fragment_type, depth);
+                }
+            }
+        }
+    }
+}
+
+struct Scanner<'a> {
+    lexer: Lexer<'a>,
+    peeked: Option<Token<'a>>,
+    normalized: String,
+}
+
+impl<'a> Scanner<'a> {
+    fn next(&mut self) -> Option<Token<'a>> {
+        if let Some(token) = self.peeked.take() {
+            return Some(token);
+        }
+        let token = match self.lexer.next() {
+            Some(Ok(token)) => token.item,
+            _ => return None,
+        };
+        self.push_normalized(&token);
+        Some(token)
+    }
+
+    fn peek(&mut self) -> Option<&Token<'a>> {
+        if self.peeked.is_none() {
+            self.peeked = Some(self.next()?);
+        }
+        self.peeked.as_ref()
+    }
+
+    fn name(&mut self) -> Option<String> {
+        match self.next()? {
+            Token::Name(name) => Some(name.to_owned()),
+            _ => None,
+        }
+    }
+
+    fn push_normalized(&mut self, token: &Token<'a>) {
+        let s: Cow<'_, str> = match *token {
+            Token::Name(name) => name.into(),
+            Token::Int(n) => n.to_string().into(),
+            Token::Float(n) => {
+                // A decimal point is always written, so that it is distinguished from
+                // the integer with the same value.
+                let mut s = n.to_string();
+                if !s.contains('.') {
+                    s.push_str(".0");
+                }
+                s.into()
+            }
+            Token::String(ref s) => serde_json::to_string(s)
+                .expect("a string should be serialized")
+                .into(),
+            Token::ExclamationMark => "!".into(),
+            Token::Dollar => "$".into(),
+            Token::ParenOpen => "(".into(),
+            Token::ParenClose => ")".into(),
+            Token::BracketOpen => "[".into(),
+            Token::BracketClose => "]".into(),
+            Token::CurlyOpen => "{".into(),
+            Token::CurlyClose => "}".into(),
+            Token::Ellipsis => "...".into(),
+            Token::Colon => ":".into(),
+            Token::Equals => "=".into(),
+            Token::At => "@".into(),
+            Token::Pipe => "|".into(),
+            Token::EndOfFile => return,
+        };
+        if !self.normalized.is_empty() {
+            self.normalized.push(' ');
+        }
+        self.normalized.push_str(&s);
+    }
+
+    // Skips the variable definitions, type conditions and directives.
+    // The default values of variables may contain braces, so those inside of
+    // parentheses are ignored.
+    fn skip_until_selection_set(&mut self, mut token: Token<'a>) -> Option<()> {
+        let mut depth = 0usize;
+        loop {
+            match token {
+                Token::ParenOpen => depth += 1,
+                Token::ParenClose => depth = depth.checked_sub(1)?,
+                Token::CurlyOpen if depth == 0 => return Some(()),
+                Token::EndOfFile => return None,
+                _ => {}
+            }
+            token = self.next()?;
+        }
+    }
+
+    // Skips the balanced parentheses, such as arguments, if exist.
+    fn skip_parens(&mut self) -> Option<()> {
+        if *self.peek()? != Token::ParenOpen {
+            return Some(());
+        }
+        let mut depth = 0usize;
+        loop {
+            match self.next()? {
+                Token::ParenOpen => depth += 1,
+                Token::ParenClose => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some(());
+                    }
+                }
+                Token::EndOfFile => return None,
+                _ => {}
+            }
+        }
+    }
+
+    fn skip_directives(&mut self) -> Option<()> {
+        while *self.peek()? == Token::At {
+            self.next()?;
+            self.name()?;
+            self.skip_parens()?;
+        }
+        Some(())
+    }
+
+    // Parses a selection set until the closing brace, after the opening one is consumed.
+    fn selection_set(&mut self) -> Option<Vec<Selection>> {
+        let mut selections = vec![];
+        loop {
+            match self.next()? {
+                Token::CurlyClose => return Some(selections),
+                Token::Name(name) => {
+                    let mut name = name.to_owned();
+                    if *self.peek()? == Token::Colon {
+                        // The alias is replaced with the actual field name.
+                        self.next()?;
+                        name = self.name()?;
+                    }
+                    self.skip_parens()?;
+                    self.skip_directives()?;
+                    let selection_set = self.nested_selection_set()?;
+                    selections.push(Selection::Field {
+                        name,
+                        selection_set,
+                    });
+                }
+                Token::Ellipsis => {
+                    let name = match *self.peek()? {
+                        Token::Name(name) => Some(name),
+                        _ => None,
+                    };
+                    match name {
+                        Some("on") => {
+                            self.next()?;
+                            let type_condition = Some(self.name()?);
+                            self.skip_directives()?;
+                            selections.push(Selection::InlineFragment {
+                                type_condition,
+                                selection_set: self.nested_selection_set()?,
+                            });
+                        }
+                        Some(name) => {
+                            self.next()?;
+                            self.skip_directives()?;
+                            selections.push(Selection::FragmentSpread(name.to_owned()));
+                        }
+                        None => {
+                            self.skip_directives()?;
+                            selections.push(Selection::InlineFragment {
+                                type_condition: None,
+                                selection_set: self.nested_selection_set()?,
+                            });
+                        }
+                    }
+                }
+                _ => return None,
+            }
+        }
+    }
+
+    fn nested_selection_set(&mut self) -> Option<Vec<Selection>> {
+        if *self.peek()? == Token::CurlyOpen {
+            self.next()?;
+            self.selection_set()
+        } else {
+            Some(vec![])
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Document, OperationKind};
+
+    fn operation_kind(source: &str, name: Option<&str>) -> Option<OperationKind> {
+        Document::parse(source)?.operation(name).map(|op| op.kind())
+    }
+
+    #[test]
+    fn test_operation_kind() {
+        assert_eq!(
+            operation_kind("{ hero { name } }", None),
+            Some(OperationKind::Query)
+        );
+        assert_eq!(
+            operation_kind("mutation M($x: In = { a: 1 }) { add(x: $x) }", None),
+            Some(OperationKind::Mutation)
+        );
+
+        let source = r#"
+            query Q { hero { ...F } }
+            fragment F on Character { name }
+            mutation M { add }
+        "#;
+        assert_eq!(
+            operation_kind(source, Some("Q")),
+            Some(OperationKind::Query)
+        );
+        assert_eq!(
+            operation_kind(source, Some("M")),
+            Some(OperationKind::Mutation)
+        );
+        assert_eq!(operation_kind(source, None), None);
+        assert_eq!(operation_kind(source, Some("F")), None);
+    }
+
+    #[test]
+    fn test_normalized() {
+        let document = Document::parse(
+            r#"
+            # comment
+            query Q($id: ID!) {
+                human(id: $id) { name, friends { name } }
+                droid(id: "2001\n") { name }
+            }
+        "#,
+        )
+        .unwrap();
+        assert_eq!(
+            document.normalized(),
+            r#"query Q ( $ id : ID ! ) { human ( id : $ id ) { name friends { name } } droid ( id : "2001\n" ) { name } }"#
+        );
+    }
+
+    #[test]
+    fn test_normalized_numbers() {
+        let normalized = |source| Document::parse(source).unwrap().normalized().to_owned();
+        assert_eq!(normalized("{ f(x: 1) }"), "{ f ( x : 1 ) }");
+        assert_eq!(normalized("{ f(x: 1.0) }"), "{ f ( x : 1.0 ) }");
+        assert_eq!(normalized("{ f(x: 1e2) }"), "{ f ( x : 100.0 ) }");
+        assert_eq!(normalized("{ f(x: 1.5) }"), "{ f ( x : 1.5 ) }");
+    }
+
+    #[test]
+    fn test_root_fields() {
+        let document = Document::parse(
+            r#"
+            mutation M { a: addHuman { id } ...F ... on Mutation { removeHuman } }
+            fragment F on Mutation { addHuman { name } ...G }
+            fragment G on Mutation { ...F, addDroid }
+        "#,
+        )
+        .unwrap();
+        let operation = document.operation(None).unwrap();
+        assert_eq!(
+            document.root_fields(operation),
+            vec!["addHuman", "addDroid", "removeHuman"]
+        );
+    }
+
+    #[test]
+    fn test_broken_document() {
+        assert!(Document::parse("{ hero { name }").is_none());
+        assert!(Document::parse("query Q ? { hero }").is_none());
+    }
+}
@@ -0,0 +1,270 @@
+use http::StatusCode;
+use log::Level;
+use serde_json;
+use std::fmt;
+use std::fmt::Write;
+use std::time::Duration;
+
+use document::OperationKind;
+use request::{fnv1a, SingleRequest};
+use request_id::RequestId;
+
+/// The target of the access log records.
+const TARGET: &str = "finchers_juniper::access_log";
+
+/// The configuration of the access log of GraphQL operations.
+///
+/// When it is enabled, a record is emitted through the `log` crate with the target
+/// `finchers_juniper::access_log` for each executed operation, in the following format:
+///
+/// ```text
+/// operation_name=HeroName operation_type=query document_hash=1b2e0a5c9d3f4e67 duration_us=1532 errors=0 batch_index=- status=200 variables="{\"episode\":\"[REDACTED]\"}" request_id=a1b2c3d4
+/// ```
+///
+/// The values containing whitespaces, `"`, `=` or `\` are written as JSON strings, and
+/// the missing values are written as `-`. The values of variables are redacted by default.
+/// The operations served without being executed, e.g. from the response cache or
+/// an identical request in flight, are also recorded with the duration `-`, and
+/// the operations aborted by a panic in the resolvers are recorded with the status 500.
+/// The identifier of the request is available if the context is constructed with
+/// [`request_id`].
+///
+/// [`request_id`]: ../request_id/fn.request_id.html
+#[derive(Debug, Clone, Copy)]
+pub struct AccessLog {
+    level: Level,
+    redact_variables: bool,
+}
+
+impl Default for AccessLog {
+    fn default() -> AccessLog {
+        AccessLog {
+            level: Level::Info,
+            redact_variables: true,
+        }
+    }
+}
+
+impl AccessLog {
+    /// Creates a new `AccessLog` with the default configuration.
+    ///
+    /// By default, the records are emitted at the `INFO` level.
+    pub fn new() -> AccessLog {
+        AccessLog::default()
+    }
+
+    /// Sets the level of the records.
+    pub fn level(self, level: Level) -> AccessLog {
+        AccessLog { level, ..self }
+    }
+
+    /// Specifies whether to redact the values of variables.
+    pub fn redact_variables(self, redact_variables: bool) -> AccessLog {
+        AccessLog {
+            redact_variables,
+            ..self
+        }
+    }
+
+    pub(crate) fn log(
+        &self,
+        request: &SingleRequest,
+        batch_index: Option<usize>,
+        duration: Option<Duration>,
+        response: &serde_json::Value,
+        status: StatusCode,
+        request_id: Option<&RequestId>,
+    ) {
+        if log_enabled!(target: TARGET, self.level) {
+            let record = self.format(request, batch_index, duration, response, status, request_id);
+            log!(target: TARGET, self.level, "{}", record);
+        }
+    }
+
+    fn format(
+        &self,
+        request: &SingleRequest,
+        batch_index: Option<usize>,
+        duration: Option<Duration>,
+        response: &serde_json::Value,
+        status: StatusCode,
+        request_id: Option<&RequestId>,
+    ) -> String {
+        let document = request.document();
+        let operation = document.and_then(|document| document.operation(request.operation_name()));
+        let operation_name = request
+            .operation_name()
+            .or_else(|| operation.and_then(|operation| operation.name()));
+        let operation_type = operation.map(|operation| match operation.kind() {
+            OperationKind::Query => "query",
+            OperationKind::Mutation => "mutation",
+            OperationKind::Subscription => "subscription",
+        });
+
+        let document_hash = fnv1a(
+            document
+                .map_or(request.query(), |document| document.normalized())
+                .as_bytes(),
+        );
+        let errors = response
+            .get("errors")
+            .and_then(|errors| errors.as_array())
+            .map_or(0, |errors| errors.len());
+        let variables = request.raw_variables().map(|variables| {
+            let mut variables = json!(variables);
+            if self.redact_variables {
+                if let Some(variables) = variables.as_object_mut() {
+                    for value in variables.values_mut() {
+                        *value = json!("[REDACTED]");
+                    }
+                }
+            }
+            variables.to_string()
+        });
+
+        let duration = duration.map(format_micros);
+        let batch_index = batch_index.map(|i| i.to_string());
+
+        let mut record = String::new();
+        let _ = write!(
+            record,
+            "operation_name={} operation_type={} document_hash={:016x} duration_us={} \
+             errors={} batch_index={} status={} variables={} request_id={}",
+            Value(operation_name),
+            Value(operation_type),
+            document_hash,
+            Value(duration.as_ref().map(String::as_str)),
+            errors,
+            Value(batch_index.as_ref().map(String::as_str)),
+            status.as_u16(),
+            Value(variables.as_ref().map(String::as_str)),
+            Value(request_id.map(RequestId::as_str)),
+        );
+        record
+    }
+}
+
+fn format_micros(duration: Duration) -> String {
+    (duration.as_secs() * 1_000_000 + u64::from(duration.subsec_micros())).to_string()
+}
+
+// Returns `true` if the value containing the character must be quoted, so that the record
+// can be split into the pairs unambiguously.
+fn needs_quote(c: char) -> bool {
+    c.is_whitespace() || c == '"' || c == '=' || c == '\\'
+}
+
+// A value in the access log record.
+struct Value<'a>(Option<&'a str>);
+
+impl<'a> fmt::Display for Value<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            None => f.write_str("-"),
+            Some(s) if s.is_empty() || s.contains(needs_quote) => {
+                let quoted = serde_json::to_string(s).map_err(|_| fmt::Error)?;
+                f.write_str(&quoted)
+            }
+            Some(s) => f.write_str(s),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use finchers::prelude::*;
+    use finchers::test;
+    use http::{Request, StatusCode};
+    use juniper::InputValue;
+    use log;
+    use log::{LevelFilter, Log, Metadata, Record};
+    use std::cell::RefCell;
+    use std::time::Duration;
+
+    use super::{AccessLog, Value, TARGET};
+    use execute::current_thread;
+    use request::GraphQLRequest;
+    use testing::{probe_schema, Probe};
+
+    // Collects the access log records emitted on the current thread.
+    struct Capture;
+
+    thread_local!(static RECORDS: RefCell<Vec<String>> = RefCell::new(vec![]));
+
+    impl Log for Capture {
+        fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+            metadata.target() == TARGET
+        }
+
+        fn log(&self, record: &Record<'_>) {
+            if self.enabled(record.metadata()) {
+                RECORDS.with(|records| records.borrow_mut().push(record.args().to_string()));
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    static CAPTURE: Capture = Capture;
+
+    fn format(access_log: AccessLog, request: &GraphQLRequest) -> String {
+        access_log.format(
+            &request.requests()[0],
+            None,
+            Some(Duration::from_micros(1500)),
+            &json!({ "data": null, "errors": [{ "message": "oops" }] }),
+            StatusCode::OK,
+            None,
+        )
+    }
+
+    #[test]
+    fn test_format() {
+        let request = GraphQLRequest::single(
+            "query Hero($episode: Episode) { hero(episode: $episode) { name } }".into(),
+            None,
+            Some(InputValue::object(
+                vec![("episode", InputValue::enum_value("NEW HOPE"))]
+                    .into_iter()
+                    .collect(),
+            )),
+        );
+
+        let record = format(AccessLog::new(), &request);
+        assert!(record.starts_with("operation_name=Hero operation_type=query document_hash="));
+        assert!(record.ends_with(
+            r#"duration_us=1500 errors=1 batch_index=- status=200 variables="{\"episode\":\"[REDACTED]\"}" request_id=-"#
+        ));
+
+        let record = format(AccessLog::new().redact_variables(false), &request);
+        assert!(record.ends_with(r#"variables="{\"episode\":\"NEW HOPE\"}" request_id=-"#));
+    }
+    #[test]
+    fn test_quoted_values() {
+        assert_eq!(Value(None).to_string(), "-");
+        assert_eq!(Value(Some("")).to_string(), r#""""#);
+        assert_eq!(Value(Some("Hero")).to_string(), "Hero");
+        assert_eq!(Value(Some("a b")).to_string(), r#""a b""#);
+        assert_eq!(Value(Some("a=b")).to_string(), r#""a=b""#);
+        assert_eq!(Value(Some(r#"a"b"#)).to_string(), r#""a\"b""#);
+        assert_eq!(Value(Some(r"a\b")).to_string(), r#""a\\b""#);
+    }
+
+    #[test]
+    fn test_panicked_operation() {
+        let _ = log::set_logger(&CAPTURE);
+        log::set_max_level(LevelFilter::Info);
+
+        let endpoint = endpoint::cloned(Probe::new())
+            .wrap(current_thread(probe_schema()).access_log(AccessLog::new()));
+        let response = test::runner(endpoint)
+            .perform(Request::get("/?query=query%20Panicked%20%7B%20panic%20%7D"))
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        let records = RECORDS.with(|records| records.borrow().clone());
+        assert_eq!(records.len(), 1);
+        assert!(records[0].starts_with("operation_name=Panicked operation_type=query "));
+        assert!(records[0].contains(" errors=1 batch_index=- status=500 "));
+    }
+}
@@ -0,0 +1,257 @@
+use http::StatusCode;
+use juniper::InputValue;
+
+use document::OperationKind;
+use request::{GraphQLRequest, GraphQLResponse, SingleRequest};
+
+/// The type of a GraphQL operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OperationType {
+    #[allow(missing_docs)]
+    Query,
+    #[allow(missing_docs)]
+    Mutation,
+    #[allow(missing_docs)]
+    Subscription,
+}
+
+/// The summary of a GraphQL operation passed to the authorization hook.
+#[derive(Debug)]
+pub struct OperationInfo<'a> {
+    operation_type: OperationType,
+    name: Option<&'a str>,
+    root_fields: Vec<&'a str>,
+    request: &'a SingleRequest,
+}
+
+impl<'a> OperationInfo<'a> {
+    /// Returns the type of this operation.
+    pub fn operation_type(&self) -> OperationType {
+        self.operation_type
+    }
+
+    /// Returns the name of this operation, if available.
+    pub fn name(&self) -> Option<&'a str> {
+        self.name
+    }
+
+    /// Returns the names of the fields selected on the root type, including the ones
+    /// selected in the fragments.  The aliases are replaced with the actual field names.
+    pub fn root_fields(&self) -> &[&'a str] {
+        &self.root_fields
+    }
+
+    /// Returns the source of the document which contains this operation.
+    pub fn query(&self) -> &'a str {
+        self.request.query()
+    }
+
+    /// Returns the variables passed to this operation, if available.
+    pub fn variables(&self) -> Option<&'a InputValue> {
+        self.request.raw_variables()
+    }
+}
+
+/// The decision made by the authorization hook.
+#[derive(Debug)]
+pub enum Authorization {
+    /// Executes the operation as it is.
+    Allow,
+
+    /// Rejects the whole request with the specified message.
+    ///
+    /// The response has the status `403 Forbidden` and contains a GraphQL error
+    /// with the error code `FORBIDDEN`.
+    Deny(String),
+
+    /// Executes the specified operation instead.
+    Rewrite {
+        #[allow(missing_docs)]
+        query: String,
+        #[allow(missing_docs)]
+        operation_name: Option<String>,
+        #[allow(missing_docs)]
+        variables: Option<InputValue>,
+    },
+}
+
+/// A hook which decides whether to execute each GraphQL operation.
+///
+/// This trait is implemented for the functions which take the context and the summary
+/// of an operation, and for `()`, which allows all operations.
+/// See [`Custom::authorize`] for details.
+///
+/// [`Custom::authorize`]: ./struct.Custom.html#method.authorize
+pub trait AuthorizationHook<CtxT> {
+    /// Makes the decision on the specified operation.
+    fn authorize(&self, context: &CtxT, operation: &OperationInfo<'_>) -> Authorization;
+}
+
+impl<CtxT> AuthorizationHook<CtxT> for () {
+    fn authorize(&self, _: &CtxT, _: &OperationInfo<'_>) -> Authorization {
+        Authorization::Allow
+    }
+}
+
+impl<CtxT, F> AuthorizationHook<CtxT> for F
+where
+    F: Fn(&CtxT, &OperationInfo<'_>) -> Authorization,
+{
+    fn authorize(&self, context: &CtxT, operation: &OperationInfo<'_>) -> Authorization {
+        (*self)(context, operation)
+    }
+}
+
+// Checks the operations in the request with the hook, and returns the response which
+// rejects the request, or `None` if all operations are allowed.
+pub(crate) fn check<CtxT, A>(
+    hook: &A,
+    request: &mut GraphQLRequest,
+    context: &CtxT,
+) -> Option<GraphQLResponse>
+where
+    A: AuthorizationHook<CtxT>,
+{
+    let mut rewrites = vec![];
+    for (index, single) in request.requests().iter().enumerate() {
+        let decision = {
+            let operation = single.document().and_then(|document| {
+                Some((document, document.operation(single.operation_name())?))
+            });
+            let (document, operation) = match operation {
+                Some(operation) => operation,
+                None => {
+                    return Some(GraphQLResponse::error_for(
+                        request,
+                        StatusCode::BAD_REQUEST,
+                        "Unable to determine the operation to be executed",
+                        "BAD_REQUEST",
+                    ))
+                }
+            };
+            let info = OperationInfo {
+                operation_type: match operation.kind() {
+                    OperationKind::Query => OperationType::Query,
+                    OperationKind::Mutation => OperationType::Mutation,
+                    OperationKind::Subscription => OperationType::Subscription,
+                },
+                name: operation.name(),
+                root_fields: document.root_fields(operation),
+                request: single,
+            };
+            hook.authorize(context, &info)
+        };
+        match decision {
+            Authorization::Allow => {}
+            Authorization::Deny(message) => {
+                return Some(GraphQLResponse::error_for(
+                    request,
+                    StatusCode::FORBIDDEN,
+                    &message,
+                    "FORBIDDEN",
+                ))
+            }
+            Authorization::Rewrite {
+                query,
+                operation_name,
+                variables,
+            } => rewrites.push((index, SingleRequest::new(query, operation_name, variables))),
+        }
+    }
+
+    let requests = request.requests_mut();
+    for (index, rewritten) in rewrites {
+        requests[index] = rewritten;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use finchers::prelude::*;
+    use finchers::test;
+    use http::{Request, StatusCode};
+    use juniper::tests::model::Database;
+
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::{Authorization, OperationInfo, OperationType};
+    use execute::{current_thread, InMemoryCache};
+    use testing::{database, database_schema, json_body};
+
+    #[test]
+    fn test_authorize() {
+        let schema = database_schema();
+        let endpoint = database().wrap(current_thread(schema).authorize(
+            |_: &Database, op: &OperationInfo<'_>| {
+                assert_eq!(op.operation_type(), OperationType::Query);
+                if op.root_fields().contains(&"droid") {
+                    Authorization::Deny("droids are classified".into())
+                } else if op.name() == Some("Old") {
+                    Authorization::Rewrite {
+                        query: "{ hero { id } }".into(),
+                        operation_name: None,
+                        variables: None,
+                    }
+                } else {
+                    Authorization::Allow
+                }
+            },
+        ));
+        let mut runner = test::runner(endpoint);
+        let mut perform = |query: &str| {
+            let response = runner.perform(Request::get(query)).unwrap();
+            let body = json_body(&response);
+            (response.status(), body)
+        };
+
+        let (status, body) = perform("/?query={hero{name}}");
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"]["hero"]["name"], "R2-D2");
+
+        let (status, body) = perform("/?query={hero{name}%20d:droid(id:%222001%22){id}}");
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(body["errors"][0]["message"], "droids are classified");
+        assert_eq!(body["errors"][0]["extensions"]["code"], "FORBIDDEN");
+
+        let (status, body) = perform("/?query=query%20Old{hero{name}}");
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"], json!({ "hero": { "id": "2001" } }));
+
+        let (status, body) = perform("/?query={hero{name}");
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["errors"][0]["extensions"]["code"], "BAD_REQUEST");
+    }
+
+    #[test]
+    fn test_authorize_before_cache() {
+        let schema = database_schema();
+        let denied = Arc::new(AtomicBool::new(false));
+        let cache = InMemoryCache::new(16, Duration::from_secs(3600));
+        let endpoint = database().wrap(
+            current_thread(schema)
+                .authorize({
+                    let denied = denied.clone();
+                    move |_: &Database, _: &OperationInfo<'_>| {
+                        if denied.load(Ordering::SeqCst) {
+                            Authorization::Deny("denied".into())
+                        } else {
+                            Authorization::Allow
+                        }
+                    }
+                })
+                .response_cache(Arc::new(cache), |_: &Database| Some("".into())),
+        );
+        let mut runner = test::runner(endpoint);
+
+        let response = runner.perform("/?query={hero{name}}").unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // the cached response is not returned to the denied request.
+        denied.store(true, Ordering::SeqCst);
+        let response = runner.perform("/?query={hero{name}}").unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+}
@@ -0,0 +1,418 @@
+use finchers::error::Error;
+
+use futures::{Async, Future, Poll};
+use serde_json;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use super::custom::ExecutionStrategy;
+use request::{GraphQLRequest, GraphQLResponse};
+
+/// Trait representing a storage of the GraphQL responses.
+pub trait ResponseCache {
+    /// Returns the cached response associated with the specified key, if available.
+    fn get(&self, key: &CacheKey) -> Option<GraphQLResponse>;
+
+    /// Stores the response with the specified key.
+    fn put(&self, key: CacheKey, response: GraphQLResponse);
+}
+
+impl<C: ResponseCache + ?Sized> ResponseCache for Arc<C> {
+    fn get(&self, key: &CacheKey) -> Option<GraphQLResponse> {
+        (**self).get(key)
+    }
+
+    fn put(&self, key: CacheKey, response: GraphQLResponse) {
+        (**self).put(key, response)
+    }
+}
+
+/// The key of cached responses.
+///
+/// It consists of the normalized documents, the operation names and the variables
+/// of the request, and the key derived from the context.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    operations: Vec<(String, Option<String>, String)>,
+    context: String,
+}
+
+impl CacheKey {
+    fn new(request: &GraphQLRequest, context: String) -> Option<CacheKey> {
+        let operations = request
+            .requests()
+            .iter()
+            .map(|request| {
+                let document = request.document()?;
+                let variables = serde_json::to_value(request.raw_variables()).ok()?;
+                let variables = serde_json::to_string(&sort_keys(variables)).ok()?;
+                Some((
+                    document.normalized().to_owned(),
+                    request.operation_name().map(ToOwned::to_owned),
+                    variables,
+                ))
+            })
+            .collect::<Option<_>>()?;
+        Some(CacheKey {
+            operations,
+            context,
+        })
+    }
+
+    /// Returns the key derived from the context.
+    pub fn context(&self) -> &str {
+        &self.context
+    }
+}
+
+// Sorts the entries of the objects, so that the order of the variables does not affect the key.
+fn sort_keys(value: serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Object(map) => {
+            let mut entries: Vec<_> = map.into_iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            serde_json::Value::Object(
+                entries
+                    .into_iter()
+                    .map(|(name, value)| (name, sort_keys(value)))
+                    .collect(),
+            )
+        }
+        serde_json::Value::Array(values) => {
+            serde_json::Value::Array(values.into_iter().map(sort_keys).collect())
+        }
+        value => value,
+    }
+}
+
+/// An in-memory implementation of `ResponseCache`.
+///
+/// The entries expire after the specified time to live, and the least recently used
+/// entry is evicted when the number of entries exceeds the capacity.
+#[derive(Debug)]
+pub struct InMemoryCache {
+    inner: Mutex<Lru>,
+    capacity: usize,
+    ttl: Duration,
+}
+
+#[derive(Debug, Default)]
+struct Lru {
+    entries: HashMap<CacheKey, Entry>,
+    recency: BTreeMap<u64, CacheKey>,
+    tick: u64,
+}
+
+#[derive(Debug)]
+struct Entry {
+    response: GraphQLResponse,
+    expires_at: Instant,
+    last_used: u64,
+}
+
+impl Lru {
+    fn touch(&mut self, key: &CacheKey) -> Option<&Entry> {
+        self.tick += 1;
+        let entry = self.entries.get_mut(key)?;
+        self.recency.remove(&entry.last_used);
+        self.recency.insert(self.tick, key.clone());
+        entry.last_used = self.tick;
+        Some(entry)
+    }
+
+    fn remove(&mut self, key: &CacheKey) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.recency.remove(&entry.last_used);
+        }
+    }
+}
+
+impl InMemoryCache {
+    /// Creates a new `InMemoryCache` with the specified capacity and time to live.
+    pub fn new(capacity: usize, ttl: Duration) -> InMemoryCache {
+        assert!(capacity > 0, "capacity must be positive");
+        InMemoryCache {
+            inner: Mutex::new(Lru::default()),
+            capacity,
+            ttl,
+        }
+    }
+
+    /// Returns the number of entries in this cache, including the expired ones
+    /// which have not been evicted yet.
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().entries.len()
+    }
+
+    /// Returns `true` if this cache has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes all entries from this cache.
+    pub fn clear(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.entries.clear();
+        inner.recency.clear();
+    }
+}
+
+impl ResponseCache for InMemoryCache {
+    fn get(&self, key: &CacheKey) -> Option<GraphQLResponse> {
+        let mut inner = self.inner.lock().unwrap();
+        let now = Instant::now();
+        match inner.touch(key) {
+            Some(entry) if entry.expires_at > now => return Some(entry.response.clone()),
+            Some(..) => {}
+            None => return None,
+        }
+        inner.remove(key);
+        None
+    }
+
+    fn put(&self, key: CacheKey, response: GraphQLResponse) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.remove(&key);
+        inner.tick += 1;
+        let tick = inner.tick;
+        inner.recency.insert(tick, key.clone());
+        inner.entries.insert(
+            key,
+            Entry {
+                response,
+                expires_at: Instant::now() + self.ttl,
+                last_used: tick,
+            },
+        );
+        while inner.entries.len() > self.capacity {
+            let oldest = match inner.recency.keys().next() {
+                Some(&tick) => tick,
+                None => break,
+            };
+            if let Some(key) = inner.recency.remove(&oldest) {
+                inner.entries.remove(&key);
+            }
+        }
+    }
+}
+
+/// An execution strategy which serves the GraphQL queries from a `ResponseCache`.
+///
+/// The cache is consulted before the request is passed to the underlying strategy,
+/// and the response is stored after the execution only if it contains no errors.
+/// The requests containing a mutation (or an operation which cannot be determined
+/// before execution) and the requests with the tracing extension enabled always
+/// bypass the cache.
+///
+/// The value of this type is created by [`Custom::response_cache`].
+///
+/// [`Custom::response_cache`]: ./struct.Custom.html#method.response_cache
+pub struct Cached<St, C, F> {
+    inner: St,
+    cache: Arc<C>,
+    key_fn: F,
+}
+
+impl<St, C, F> fmt::Debug for Cached<St, C, F>
+where
+    St: fmt::Debug,
+    C: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cached")
+            .field("inner", &self.inner)
+            .field("cache", &self.cache)
+            .finish()
+    }
+}
+
+impl<St, C, F> Cached<St, C, F> {
+    pub(crate) fn new(inner: St, cache: C, key_fn: F) -> Cached<St, C, F> {
+        Cached {
+            inner,
+            cache: Arc::new(cache),
+            key_fn,
+        }
+    }
+
+    /// Returns a reference to the underlying execution strategy.
+    pub fn inner(&self) -> &St {
+        &self.inner
+    }
+
+    /// Returns a reference to the response cache.
+    pub fn cache(&self) -> &C {
+        &self.cache
+    }
+}
+
+impl<S, CtxT, St, C, F> ExecutionStrategy<S, CtxT> for Cached<St, C, F>
+where
+    St: ExecutionStrategy<S, CtxT>,
+    C: ResponseCache,
+    F: Fn(&CtxT) -> Option<String>,
+{
+    type Future = CachedFuture<St::Future, C>;
+
+    fn execute(&self, request: GraphQLRequest, context: CtxT, schema: Arc<S>) -> Self::Future {
+        let key = if request.is_query() && !request.is_tracing() {
+            (self.key_fn)(&context).and_then(|context| CacheKey::new(&request, context))
+        } else {
+            None
+        };
+        if let Some(ref key) = key {
+            if let Some(mut response) = self.cache.get(key) {
+                trace!("serve a GraphQL response from the cache");
+                request.observe_replayed(&response);
+                // The cached response may have been produced for a POST request.
+                response.set_conditional(request.is_conditional());
+                return CachedFuture(State::Hit(Some(response)));
+            }
+        }
+        CachedFuture(State::Miss {
+            future: self.inner.execute(request, context, schema),
+            cache: self.cache.clone(),
+            key,
+        })
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct CachedFuture<Fut, C>(State<Fut, C>);
+
+enum State<Fut, C> {
+    Hit(Option<GraphQLResponse>),
+    Miss {
+        future: Fut,
+        cache: Arc<C>,
+        key: Option<CacheKey>,
+    },
+}
+
+impl<Fut, C> Future for CachedFuture<Fut, C>
+where
+    Fut: Future<Item = GraphQLResponse, Error = Error>,
+    C: ResponseCache,
+{
+    type Item = GraphQLResponse;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match self.0 {
+            State::Hit(ref mut response) => Ok(Async::Ready(
+                response.take().expect("the future has already polled"),
+            )),
+            State::Miss {
+                ref mut future,
+                ref cache,
+                ref mut key,
+            } => {
+                let response = try_ready!(future.poll());
+                if let Some(key) = key.take() {
+                    if response.status().is_success() && !response.has_errors() {
+                        cache.put(key, response.clone());
+                    }
+                }
+                Ok(Async::Ready(response))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use finchers::prelude::*;
+    use finchers::test;
+    use http::Request;
+    use juniper::tests::model::Database;
+    use serde_json;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::{CacheKey, InMemoryCache, ResponseCache};
+    use execute::current_thread;
+    use request::{GraphQLRequest, GraphQLResponse};
+    use testing::{database, database_schema};
+
+    fn key(query: &str) -> CacheKey {
+        CacheKey::new(
+            &GraphQLRequest::single(query.into(), None, None),
+            String::new(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_cache_key_normalized() {
+        assert_eq!(
+            key("{ hero { name } }"),
+            key("{\n  hero {\n    name\n  }\n}")
+        );
+        assert_ne!(key("{ hero { name } }"), key("{ hero { id } }"));
+        assert_ne!(key("{ f(x: 1) }"), key("{ f(x: 1.0) }"));
+    }
+
+    #[test]
+    fn test_cache_key_variables_order() {
+        let key = |variables: &str| {
+            let variables = serde_json::from_str(variables).unwrap();
+            CacheKey::new(
+                &GraphQLRequest::single("{ a }".into(), None, Some(variables)),
+                String::new(),
+            )
+            .unwrap()
+        };
+        assert_eq!(
+            key(r#"{"a": 1, "b": {"c": 2, "d": [{"e": 3, "f": 4}]}}"#),
+            key(r#"{"b": {"d": [{"f": 4, "e": 3}], "c": 2}, "a": 1}"#)
+        );
+        assert_ne!(key(r#"{"a": 1, "b": 2}"#), key(r#"{"a": 2, "b": 1}"#));
+    }
+
+    #[test]
+    fn test_in_memory_cache() {
+        let cache = InMemoryCache::new(2, Duration::from_secs(3600));
+        let response = GraphQLResponse::internal_server_error;
+        cache.put(key("{ a }"), response());
+        cache.put(key("{ b }"), response());
+        assert!(cache.get(&key("{ a }")).is_some());
+        cache.put(key("{ c }"), response());
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get(&key("{ b }")).is_none());
+        assert!(cache.get(&key("{ a }")).is_some());
+    }
+
+    #[test]
+    fn test_in_memory_cache_expired() {
+        let cache = InMemoryCache::new(2, Duration::from_secs(0));
+        cache.put(key("{ a }"), GraphQLResponse::internal_server_error());
+        assert_eq!(cache.len(), 1);
+        assert!(cache.get(&key("{ a }")).is_none());
+        assert_eq!(cache.len(), 0);
+    }
+
+    #[test]
+    fn test_cached_strategy() {
+        let schema = database_schema();
+        let cache = Arc::new(InMemoryCache::new(16, Duration::from_secs(60)));
+        let endpoint = database().wrap(
+            current_thread(schema).response_cache(cache.clone(), |_: &Database| Some("".into())),
+        );
+        let mut runner = test::runner(endpoint);
+
+        let response = runner
+            .perform(Request::get("/?query={hero{name}}"))
+            .unwrap();
+        assert!(response.status().is_success());
+        assert_eq!(cache.len(), 1);
+
+        // responses with errors are not cached.
+        let response = runner
+            .perform(Request::get("/?query={hero{unknown}}"))
+            .unwrap();
+        assert!(!response.status().is_success());
+        assert_eq!(cache.len(), 1);
+    }
+}
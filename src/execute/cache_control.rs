@@ -0,0 +1,285 @@
+use finchers::error::Error;
+
+use futures::{Async, Future, Poll};
+use juniper::{GraphQLType, RootNode};
+use std::cmp;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use super::custom::ExecutionStrategy;
+use super::Schema;
+use document::OperationKind;
+use request::{GraphQLRequest, GraphQLResponse};
+
+/// The scope of a cached response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CacheScope {
+    /// The response can be stored by shared caches, such as CDNs.
+    Public,
+    /// The response is intended for a single user.
+    Private,
+}
+
+/// A hint for caching the values of a type or a field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CacheHint {
+    max_age: u32,
+    scope: CacheScope,
+}
+
+impl CacheHint {
+    /// Creates a `CacheHint` with the specified max-age in seconds, and the public scope.
+    pub fn public(max_age: u32) -> CacheHint {
+        CacheHint {
+            max_age,
+            scope: CacheScope::Public,
+        }
+    }
+
+    /// Creates a `CacheHint` with the specified max-age in seconds, and the private scope.
+    pub fn private(max_age: u32) -> CacheHint {
+        CacheHint {
+            max_age,
+            scope: CacheScope::Private,
+        }
+    }
+
+    /// Returns the max-age of this hint, in seconds.
+    pub fn max_age(&self) -> u32 {
+        self.max_age
+    }
+
+    /// Returns the scope of this hint.
+    pub fn scope(&self) -> CacheScope {
+        self.scope
+    }
+
+    fn merge(self, other: CacheHint) -> CacheHint {
+        CacheHint {
+            max_age: cmp::min(self.max_age, other.max_age),
+            scope: if self.scope == CacheScope::Private || other.scope == CacheScope::Private {
+                CacheScope::Private
+            } else {
+                CacheScope::Public
+            },
+        }
+    }
+}
+
+/// A registry of the cache hints declared for the types and fields in a schema.
+///
+/// The cache policy of a query is the combination of the hints of all selected fields:
+/// the max-age is the minimum of them, and the scope is private if any of them is private.
+/// The hint of a field is looked up in the following order:
+///
+/// * The hint declared for the field.
+/// * The hint declared for the return type of the field.
+/// * The default max-age, if the field is a root field or returns a composite type.
+///   The other fields (returning scalars or enums) inherit the policy from their parents.
+#[derive(Debug, Clone, Default)]
+pub struct CacheHints {
+    types: HashMap<String, CacheHint>,
+    fields: HashMap<String, HashMap<String, CacheHint>>,
+    default_max_age: u32,
+}
+
+impl CacheHints {
+    /// Creates an empty `CacheHints`, whose default max-age is zero.
+    pub fn new() -> CacheHints {
+        CacheHints::default()
+    }
+
+    /// Declares the hint of the specified type.
+    pub fn type_hint(mut self, type_name: impl Into<String>, hint: CacheHint) -> CacheHints {
+        self.types.insert(type_name.into(), hint);
+        self
+    }
+
+    /// Declares the hint of the specified field.
+    pub fn field_hint(
+        mut self,
+        type_name: impl Into<String>,
+        field_name: impl Into<String>,
+        hint: CacheHint,
+    ) -> CacheHints {
+        self.fields
+            .entry(type_name.into())
+            .or_default()
+            .insert(field_name.into(), hint);
+        self
+    }
+
+    /// Sets the max-age of the fields without any hints, in seconds.
+    pub fn default_max_age(self, default_max_age: u32) -> CacheHints {
+        CacheHints {
+            default_max_age,
+            ..self
+        }
+    }
+
+    // Returns `None` if the request contains an operation other than queries.
+    fn compute<QueryT, MutationT>(
+        &self,
+        request: &GraphQLRequest,
+        root_node: &RootNode<'static, QueryT, MutationT>,
+    ) -> Option<CacheHint>
+    where
+        QueryT: GraphQLType,
+        MutationT: GraphQLType,
+    {
+        let mut policy: Option<CacheHint> = None;
+        for request in request.requests() {
+            let document = request.document()?;
+            let operation = document.operation(request.operation_name())?;
+            if operation.kind() != OperationKind::Query {
+                return None;
+            }
+            document.visit_fields(operation, root_node, |parent, field, depth| {
+                let type_name = field.field_type.innermost_name();
+                let hint = parent
+                    .name()
+                    .and_then(|parent| self.fields.get(parent))
+                    .and_then(|fields| fields.get(&field.name))
+                    .or_else(|| self.types.get(type_name))
+                    .cloned()
+                    .or_else(|| {
+                        let is_composite = root_node
+                            .schema
+                            .concrete_type_by_name(type_name)
+                            .map_or(false, |t| t.is_composite());
+                        if depth == 0 || is_composite {
+                            Some(CacheHint::public(self.default_max_age))
+                        } else {
+                            None
+                        }
+                    });
+                if let Some(hint) = hint {
+                    policy = Some(policy.map_or(hint, |policy| policy.merge(hint)));
+                }
+            });
+        }
+        policy
+    }
+}
+
+/// An execution strategy which computes the cache policy of the GraphQL queries
+/// from the hints declared in the schema.
+///
+/// The policy is attached to the response as a `Cache-Control` header, only if
+/// the request consists of queries, the execution is succeeded without any errors,
+/// and the computed max-age is positive.
+///
+/// The value of this type is created by [`Custom::cache_hints`].
+///
+/// [`Custom::cache_hints`]: ./struct.Custom.html#method.cache_hints
+#[derive(Debug)]
+pub struct CacheControl<St> {
+    inner: St,
+    hints: CacheHints,
+}
+
+impl<St> CacheControl<St> {
+    pub(crate) fn new(inner: St, hints: CacheHints) -> CacheControl<St> {
+        CacheControl { inner, hints }
+    }
+
+    /// Returns a reference to the underlying execution strategy.
+    pub fn inner(&self) -> &St {
+        &self.inner
+    }
+
+    /// Returns a reference to the registry of cache hints.
+    pub fn hints(&self) -> &CacheHints {
+        &self.hints
+    }
+}
+
+impl<S, CtxT, St> ExecutionStrategy<S, CtxT> for CacheControl<St>
+where
+    S: Schema,
+    St: ExecutionStrategy<S, CtxT>,
+{
+    type Future = CacheControlFuture<St::Future>;
+
+    fn execute(&self, request: GraphQLRequest, context: CtxT, schema: Arc<S>) -> Self::Future {
+        let hint = self
+            .hints
+            .compute(&request, schema.as_root_node())
+            .filter(|hint| hint.max_age() > 0);
+        CacheControlFuture {
+            future: self.inner.execute(request, context, schema),
+            hint,
+        }
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct CacheControlFuture<Fut> {
+    future: Fut,
+    hint: Option<CacheHint>,
+}
+
+impl<Fut> Future for CacheControlFuture<Fut>
+where
+    Fut: Future<Item = GraphQLResponse, Error = Error>,
+{
+    type Item = GraphQLResponse;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let mut response = try_ready!(self.future.poll());
+        if let Some(hint) = self.hint.take() {
+            if response.status().is_success() && !response.has_errors() {
+                response.set_cache_hint(hint);
+            }
+        }
+        Ok(Async::Ready(response))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use finchers::prelude::*;
+    use finchers::test;
+    use http::Request;
+
+    use super::{CacheHint, CacheHints};
+    use execute::current_thread;
+    use testing::{database, database_schema};
+
+    #[test]
+    fn test_cache_control() {
+        let schema = database_schema();
+        let hints = CacheHints::new()
+            .type_hint("Character", CacheHint::public(60))
+            .type_hint("Droid", CacheHint::public(120))
+            .field_hint("Human", "homePlanet", CacheHint::private(30));
+        let endpoint = database().wrap(current_thread(schema).cache_hints(hints));
+        let mut runner = test::runner(endpoint);
+
+        let mut cache_control = |query: &str| {
+            let response = runner.perform(Request::get(query)).unwrap();
+            response
+                .headers()
+                .get("cache-control")
+                .map(|value| value.to_str().unwrap().to_owned())
+        };
+
+        assert_eq!(
+            cache_control("/?query={hero{name}}"),
+            Some("max-age=60, public".into())
+        );
+        assert_eq!(
+            cache_control("/?query={droid(id:%222001%22){name}}"),
+            Some("max-age=120, public".into())
+        );
+        assert_eq!(
+            cache_control("/?query={hero{name%20...on%20Human{homePlanet}}}"),
+            Some("max-age=30, private".into())
+        );
+        // the root field without hints
+        assert_eq!(cache_control("/?query={human(id:%221000%22){name}}"), None);
+        // the response with errors
+        assert_eq!(cache_control("/?query={hero{unknown}}"), None);
+    }
+}
@@ -0,0 +1,173 @@
+//! Cancellation of the spawned GraphQL tasks.
+//!
+//! # Example
+//!
+//! ```
+//! #[macro_use]
+//! extern crate juniper;
+//! extern crate finchers_juniper;
+//!
+//! use finchers_juniper::execute::cancel;
+//! use juniper::FieldResult;
+//!
+//! struct MyContext;
+//! impl juniper::Context for MyContext {}
+//!
+//! struct Query;
+//! graphql_object!(Query: MyContext |&self| {
+//!     field sum(n: i32) -> FieldResult<i32> {
+//!         let token = cancel::current();
+//!         let mut sum = 0;
+//!         for i in 0..n {
+//!             if token.is_cancelled() {
+//!                 return Err("the request has been cancelled".into());
+//!             }
+//!             sum += i;
+//!         }
+//!         Ok(sum)
+//!     }
+//! });
+//! # fn main() {}
+//! ```
+
+use finchers::error;
+use finchers::error::Error;
+
+use http::StatusCode;
+use std::cell::RefCell;
+use std::mem;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+thread_local!(static CURRENT: RefCell<Option<CancellationToken>> = RefCell::new(None));
+
+/// A token which notifies that the client waiting for the result of GraphQL execution
+/// has gone away.
+///
+/// The spawning executors create a token for each task and cancel it when the future
+/// waiting for the task is dropped (e.g. the HTTP connection is closed).
+/// The resolvers can observe it through [`current`] and abort an expensive
+/// computation early.
+///
+/// [`current`]: ./fn.current.html
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    /// Creates a new `CancellationToken` which has not been cancelled yet.
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    /// Marks this token as cancelled.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns whether this token has been cancelled or not.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Runs the specified closure with setting this token to the current one.
+    pub(crate) fn enter<R>(&self, f: impl FnOnce() -> R) -> R {
+        #[allow(missing_debug_implementations)]
+        struct ResetOnDrop(Option<CancellationToken>);
+
+        impl Drop for ResetOnDrop {
+            fn drop(&mut self) {
+                CURRENT.with(|current| *current.borrow_mut() = self.0.take());
+            }
+        }
+
+        let prev =
+            CURRENT.with(|current| mem::replace(&mut *current.borrow_mut(), Some(self.clone())));
+        let _reset = ResetOnDrop(prev);
+        f()
+    }
+}
+
+/// Returns the cancellation token of the GraphQL task executing on the current thread.
+///
+/// If there is no such task, this function returns a token which is never cancelled.
+pub fn current() -> CancellationToken {
+    CURRENT.with(|current| current.borrow().clone().unwrap_or_default())
+}
+
+/// A guard which cancels the associated token when dropped.
+#[derive(Debug)]
+pub(crate) struct CancelOnDrop(pub(crate) CancellationToken);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        if !self.0.is_cancelled() {
+            trace!("cancel the GraphQL task");
+            self.0.cancel();
+        }
+    }
+}
+
+/// Creates an error which represents that the GraphQL task has been cancelled.
+pub(crate) fn cancelled() -> Error {
+    error::err_msg(
+        StatusCode::SERVICE_UNAVAILABLE,
+        "the GraphQL task has been cancelled",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::future;
+    use futures_cpupool::CpuPool;
+    use std::sync::Arc;
+    use tokio::runtime::Runtime;
+
+    use super::{current, CancelOnDrop, CancellationToken};
+    use execute::{nonblocking, with_spawner, Custom, ExecutionStrategy};
+    use request::GraphQLRequest;
+    use testing::{probe_schema, Probe, ProbeSchema};
+
+    #[test]
+    fn test_current_token() {
+        let token = CancellationToken::new();
+        assert!(!current().is_cancelled());
+        token.enter(|| {
+            drop(CancelOnDrop(token.clone()));
+            assert!(current().is_cancelled());
+        });
+        assert!(!current().is_cancelled());
+    }
+
+    #[test]
+    fn test_cancel_spawned_tasks() {
+        fn abandon<St>(executor: Custom<ProbeSchema, St>, rt: &mut Runtime)
+        where
+            St: ExecutionStrategy<ProbeSchema, Probe> + Send + Sync + 'static,
+            St::Future: Send + 'static,
+        {
+            let probe = Probe::new();
+            let request = GraphQLRequest::single("{ wait }".into(), None, None);
+            let future = rt
+                .block_on(future::lazy({
+                    let probe = probe.clone();
+                    move || {
+                        let future =
+                            executor
+                                .strategy()
+                                .execute(request, probe, Arc::new(probe_schema()));
+                        Ok::<_, ()>(future)
+                    }
+                }))
+                .unwrap();
+            probe.wait_started(1);
+            drop(future);
+            probe.wait_cancelled(1);
+        }
+
+        let mut rt = Runtime::new().unwrap();
+        abandon(nonblocking(probe_schema()), &mut rt);
+        abandon(with_spawner(probe_schema(), CpuPool::new(1)), &mut rt);
+    }
+}
@@ -0,0 +1,416 @@
+use finchers::error::{Error, HttpError};
+
+use futures::future::{Shared, SharedError};
+use futures::sync::oneshot;
+use futures::{Async, Future, Poll};
+use http::header::HeaderMap;
+use http::StatusCode;
+use serde_json;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+
+use super::cancel::cancelled;
+use super::custom::ExecutionStrategy;
+use request::{GraphQLRequest, GraphQLResponse};
+
+type BoxFuture = Box<dyn Future<Item = GraphQLResponse, Error = Error> + Send>;
+// The entries are tagged with the identifier of the execution, so that an execution
+// removes only its own entry.
+type InFlightMap<K> = Mutex<HashMap<(u64, K), (usize, Weak<InFlight<K>>)>>;
+
+/// An execution strategy which shares the result of identical GraphQL requests
+/// being executed concurrently.
+///
+/// The in-flight executions are keyed by the hash of the request and the key extracted
+/// from the context. While an execution is in progress, the subsequent requests with
+/// the same key wait for its completion instead of starting a new execution, and
+/// receive a copy of its response.
+///
+/// The requests containing a mutation (or an operation which cannot be determined
+/// before execution) and the requests with the tracing extension enabled are always
+/// executed by themselves.
+///
+/// The value of this type is created by [`Custom::coalesce`].
+///
+/// [`Custom::coalesce`]: ./struct.Custom.html#method.coalesce
+pub struct Coalesce<St, F, K: Hash + Eq> {
+    inner: St,
+    key_fn: F,
+    in_flight: Arc<InFlightMap<K>>,
+}
+
+impl<St, F, K> fmt::Debug for Coalesce<St, F, K>
+where
+    St: fmt::Debug,
+    K: Hash + Eq,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Coalesce")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+impl<St, F, K> Coalesce<St, F, K>
+where
+    K: Hash + Eq,
+{
+    pub(crate) fn new(inner: St, key_fn: F) -> Coalesce<St, F, K> {
+        Coalesce {
+            inner,
+            key_fn,
+            in_flight: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Returns a reference to the underlying execution strategy.
+    pub fn inner(&self) -> &St {
+        &self.inner
+    }
+
+    /// Returns the number of GraphQL executions currently shared by the waiters.
+    pub fn in_flight(&self) -> usize {
+        // The upgraded entries are dropped after releasing the lock, which is acquired again
+        // when the last reference to an entry is dropped.
+        let entries: Vec<_> = self
+            .in_flight
+            .lock()
+            .unwrap()
+            .values()
+            .filter_map(|entry| entry.1.upgrade())
+            .collect();
+        entries.len()
+    }
+}
+
+impl<S, CtxT, St, F, K> ExecutionStrategy<S, CtxT> for Coalesce<St, F, K>
+where
+    St: ExecutionStrategy<S, CtxT>,
+    St::Future: Send + 'static,
+    F: Fn(&CtxT) -> K,
+    K: Hash + Eq + Clone + Send + Sync + 'static,
+{
+    type Future = CoalesceFuture<St::Future, K>;
+
+    fn execute(&self, request: GraphQLRequest, context: CtxT, schema: Arc<S>) -> Self::Future {
+        if !request.is_query() || request.is_tracing() {
+            return CoalesceFuture(State::Direct(self.inner.execute(request, context, schema)));
+        }
+        let body = match serde_json::to_vec(&request) {
+            Ok(body) => body,
+            Err(..) => {
+                return CoalesceFuture(State::Direct(self.inner.execute(request, context, schema)))
+            }
+        };
+        let mut hasher = DefaultHasher::new();
+        body.hash(&mut hasher);
+        let key = (hasher.finish(), (self.key_fn)(&context));
+
+        // The lookup and the registration are done under the same lock, so that only one of
+        // the identical requests arriving at the same time starts the execution.  The entry
+        // is registered with a placeholder, which receives the actual future after the lock
+        // is released.
+        let mut map = self.in_flight.lock().unwrap();
+        if let Some(in_flight) = map.get(&key).and_then(|entry| entry.1.upgrade()) {
+            // The lock must be released before the last reference to the entry is dropped.
+            drop(map);
+            if in_flight.body == body {
+                trace!("join an in-flight GraphQL execution");
+                return CoalesceFuture(State::Coalesced {
+                    future: in_flight.future.clone(),
+                    in_flight,
+                    waiter: Some(request),
+                });
+            }
+            trace!("hash collision between different GraphQL requests");
+            return CoalesceFuture(State::Direct(self.inner.execute(request, context, schema)));
+        }
+        let (tx, rx) = oneshot::channel::<BoxFuture>();
+        let placeholder: BoxFuture = Box::new(rx.map_err(|_| cancelled()).and_then(|f| f));
+        let future = placeholder.shared();
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let in_flight = Arc::new(InFlight {
+            id,
+            key: key.clone(),
+            body,
+            future: future.clone(),
+            map: self.in_flight.clone(),
+        });
+        map.insert(key, (id, Arc::downgrade(&in_flight)));
+        drop(map);
+
+        let _ = tx.send(Box::new(self.inner.execute(request, context, schema)));
+        CoalesceFuture(State::Coalesced {
+            future,
+            in_flight,
+            waiter: None,
+        })
+    }
+}
+
+struct InFlight<K: Hash + Eq> {
+    id: usize,
+    key: (u64, K),
+    body: Vec<u8>,
+    future: Shared<BoxFuture>,
+    map: Arc<InFlightMap<K>>,
+}
+
+impl<K: Hash + Eq> InFlight<K> {
+    // Removes the entry so that the subsequent requests start a new execution.
+    fn complete(&self) {
+        let mut map = self.map.lock().unwrap();
+        let is_current = map.get(&self.key).map_or(false, |&(id, _)| id == self.id);
+        if is_current {
+            map.remove(&self.key);
+        }
+    }
+}
+
+impl<K: Hash + Eq> Drop for InFlight<K> {
+    fn drop(&mut self) {
+        // All waiters have gone away before completion.
+        self.complete();
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct CoalesceFuture<Fut, K: Hash + Eq>(State<Fut, K>);
+
+enum State<Fut, K: Hash + Eq> {
+    Direct(Fut),
+    Coalesced {
+        future: Shared<BoxFuture>,
+        in_flight: Arc<InFlight<K>>,
+        // The request joined the execution started by another one, which is kept for
+        // recording the metrics and the access log and for adjusting the shared response.
+        waiter: Option<GraphQLRequest>,
+    },
+}
+
+impl<Fut, K> Future for CoalesceFuture<Fut, K>
+where
+    Fut: Future<Item = GraphQLResponse, Error = Error>,
+    K: Hash + Eq,
+{
+    type Item = GraphQLResponse;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match self.0 {
+            State::Direct(ref mut future) => future.poll(),
+            State::Coalesced {
+                ref mut future,
+                ref in_flight,
+                ref waiter,
+            } => {
+                let result = match future.poll() {
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Ok(Async::Ready(response)) => {
+                        let mut response = (*response).clone();
+                        if let Some(ref request) = *waiter {
+                            request.observe_replayed(&response);
+                            // The shared response may have been produced for a POST request.
+                            response.set_conditional(request.is_conditional());
+                        }
+                        Ok(Async::Ready(response))
+                    }
+                    Err(err) => Err(CoalescedError(err).into()),
+                };
+                in_flight.complete();
+                result
+            }
+        }
+    }
+}
+
+// An error shared by all waiters of an in-flight execution.
+#[derive(Debug)]
+struct CoalescedError(SharedError<Error>);
+
+impl fmt::Display for CoalescedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&*self.0, f)
+    }
+}
+
+impl HttpError for CoalescedError {
+    fn status_code(&self) -> StatusCode {
+        self.0.status_code()
+    }
+
+    fn headers(&self, headers: &mut HeaderMap) {
+        self.0.headers(headers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use finchers::endpoint;
+    use finchers::error::Error;
+    use finchers::test;
+    use futures::future::Shared;
+    use futures::sync::oneshot;
+    use futures::Future;
+    use http::Request;
+    use juniper::tests::model::Database;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Barrier};
+    use std::thread;
+
+    use super::Coalesce;
+    use execute::ExecutionStrategy;
+    use request::{graphql_request, GraphQLRequest, GraphQLResponse};
+    use testing::{database_schema, DatabaseSchema};
+
+    struct Gated {
+        count: Arc<AtomicUsize>,
+        gate: Shared<oneshot::Receiver<()>>,
+    }
+
+    impl ExecutionStrategy<(), u32> for Gated {
+        type Future = Box<dyn Future<Item = GraphQLResponse, Error = Error> + Send>;
+
+        fn execute(&self, _: GraphQLRequest, _: u32, _: Arc<()>) -> Self::Future {
+            self.count.fetch_add(1, Ordering::SeqCst);
+            Box::new(
+                self.gate
+                    .clone()
+                    .then(|_| Ok(GraphQLResponse::internal_server_error())),
+            )
+        }
+    }
+
+    struct Deferred {
+        gate: Shared<oneshot::Receiver<()>>,
+    }
+
+    impl ExecutionStrategy<DatabaseSchema, Database> for Deferred {
+        type Future = Box<dyn Future<Item = GraphQLResponse, Error = Error> + Send>;
+
+        fn execute(
+            &self,
+            request: GraphQLRequest,
+            context: Database,
+            schema: Arc<DatabaseSchema>,
+        ) -> Self::Future {
+            Box::new(
+                self.gate
+                    .clone()
+                    .then(move |_| Ok(request.execute(&schema, &context))),
+            )
+        }
+    }
+
+    fn request(query: &str) -> GraphQLRequest {
+        GraphQLRequest::single(query.into(), None, None)
+    }
+
+    #[test]
+    fn test_coalesce() {
+        let (tx, rx) = oneshot::channel();
+        let count = Arc::new(AtomicUsize::new(0));
+        let strategy = Coalesce::new(
+            Gated {
+                count: count.clone(),
+                gate: rx.shared(),
+            },
+            |&user: &u32| user,
+        );
+        let schema = Arc::new(());
+
+        let futures = vec![
+            strategy.execute(request("{ hero { name } }"), 1, schema.clone()),
+            strategy.execute(request("{ hero { name } }"), 1, schema.clone()),
+            strategy.execute(request("{ hero { name } }"), 2, schema.clone()),
+            strategy.execute(request("mutation { add }"), 1, schema.clone()),
+            strategy.execute(request("mutation { add }"), 1, schema.clone()),
+        ];
+        assert_eq!(count.load(Ordering::SeqCst), 4);
+        assert_eq!(strategy.in_flight(), 2);
+
+        tx.send(()).unwrap();
+        for future in futures {
+            assert!(future.wait().is_ok());
+        }
+        assert_eq!(strategy.in_flight(), 0);
+
+        drop(strategy.execute(request("{ hero { name } }"), 1, schema));
+        assert_eq!(count.load(Ordering::SeqCst), 5);
+        assert_eq!(strategy.in_flight(), 0);
+    }
+
+    #[test]
+    fn test_coalesce_concurrent_requests() {
+        let (tx, rx) = oneshot::channel();
+        tx.send(()).unwrap();
+        let count = Arc::new(AtomicUsize::new(0));
+        let strategy = Arc::new(Coalesce::new(
+            Gated {
+                count: count.clone(),
+                gate: rx.shared(),
+            },
+            |&user: &u32| user,
+        ));
+
+        for i in 0..50 {
+            let barrier = Arc::new(Barrier::new(2));
+            let handles: Vec<_> = (0..2)
+                .map(|_| {
+                    let strategy = strategy.clone();
+                    let barrier = barrier.clone();
+                    thread::spawn(move || {
+                        barrier.wait();
+                        strategy.execute(request("{ hero { name } }"), 1, Arc::new(()))
+                    })
+                })
+                .collect();
+            let futures: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+            assert_eq!(count.load(Ordering::SeqCst), i + 1);
+            for future in futures {
+                assert!(future.wait().is_ok());
+            }
+        }
+    }
+
+    #[test]
+    fn test_coalesce_conditional_waiters() {
+        const QUERY: &str = "{ hero { name } }";
+        let get = || {
+            test::runner(graphql_request())
+                .apply(Request::get("/?query=%7B%20hero%20%7B%20name%20%7D%20%7D"))
+                .unwrap()
+        };
+        let has_etag = |response: GraphQLResponse| {
+            test::runner(endpoint::cloned(response))
+                .perform(Request::get("/"))
+                .unwrap()
+                .headers()
+                .contains_key("etag")
+        };
+        let schema = Arc::new(database_schema());
+
+        // A request sent by GET joins the execution started by POST, and vice versa.
+        for &leader_is_get in &[false, true] {
+            let (tx, rx) = oneshot::channel();
+            let strategy = Coalesce::new(Deferred { gate: rx.shared() }, |_: &Database| ());
+            let (leader, waiter) = if leader_is_get {
+                (get(), request(QUERY))
+            } else {
+                (request(QUERY), get())
+            };
+            let leader = strategy.execute(leader, Database::new(), schema.clone());
+            let waiter = strategy.execute(waiter, Database::new(), schema.clone());
+            assert_eq!(strategy.in_flight(), 1);
+
+            tx.send(()).unwrap();
+            assert_eq!(has_etag(leader.wait().unwrap()), leader_is_get);
+            assert_eq!(has_etag(waiter.wait().unwrap()), !leader_is_get);
+        }
+    }
+}
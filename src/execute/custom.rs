@@ -0,0 +1,538 @@
+use finchers::endpoint;
+use finchers::endpoint::wrapper::Wrapper;
+use finchers::endpoint::{ApplyContext, ApplyError, ApplyResult, Endpoint, IntoEndpoint};
+use finchers::error::{Error, HttpError};
+
+use futures::future;
+use futures::{Async, Future, Poll};
+use http::StatusCode;
+use std::hash::Hash;
+use std::sync::Arc;
+
+use super::access_log::AccessLog;
+use super::authorize::{self, AuthorizationHook};
+use super::cache::{Cached, ResponseCache};
+use super::cache_control::{CacheControl, CacheHints};
+use super::coalesce::Coalesce;
+use super::limit::{ConcurrencyLimit, Limited};
+use super::slow_log::SlowOperationLog;
+use context::Unauthorized;
+use metrics::{InFlight, Metrics};
+use request::{
+    Compression, CsrfPrevention, GraphQLRequest, GraphQLRequestEndpoint, GraphQLResponse,
+    RequestFuture,
+};
+use request_id::RequestId;
+use usage::FieldUsage;
+
+/// Trait representing the strategy for executing GraphQL requests.
+///
+/// The executors provided by this crate are defined as the combination of [`Custom`]
+/// and an implementor of this trait, and the user-defined strategies can be plugged
+/// by using [`custom`].
+///
+/// [`Custom`]: ./struct.Custom.html
+/// [`custom`]: ./fn.custom.html
+pub trait ExecutionStrategy<S, CtxT> {
+    /// The type of future returned from `execute`.
+    type Future: Future<Item = GraphQLResponse, Error = Error>;
+
+    /// Starts the execution of the specified GraphQL request with the context and schema.
+    fn execute(&self, request: GraphQLRequest, context: CtxT, schema: Arc<S>) -> Self::Future;
+}
+
+/// Create a GraphQL executor from the specified `RootNode` and the execution strategy.
+///
+/// The endpoint created by this wrapper will receive the GraphQL request and the context,
+/// and then execute it by using the specified strategy.
+pub fn custom<S, St>(schema: S, strategy: St) -> Custom<S, St> {
+    Custom {
+        schema,
+        strategy,
+        compression: None,
+        request: ::request::graphql_request(),
+        pretty: false,
+        tracing: false,
+        access_log: None,
+        slow_operation_log: None,
+        metrics: None,
+        field_usage: None,
+        authorization: None,
+    }
+}
+
+/// A GraphQL executor built from a schema and an execution strategy.
+///
+/// The type parameter `A` is the authorization hook registered by [`authorize`].
+///
+/// [`authorize`]: #method.authorize
+#[derive(Debug)]
+pub struct Custom<S, St, A = ()> {
+    schema: S,
+    strategy: St,
+    compression: Option<Compression>,
+    request: GraphQLRequestEndpoint,
+    pretty: bool,
+    tracing: bool,
+    access_log: Option<AccessLog>,
+    slow_operation_log: Option<Arc<SlowOperationLog>>,
+    metrics: Option<Arc<Metrics>>,
+    field_usage: Option<Arc<FieldUsage>>,
+    authorization: Option<A>,
+}
+
+impl<S, St, A> Custom<S, St, A> {
+    /// Returns a reference to the execution strategy used in this executor.
+    pub fn strategy(&self) -> &St {
+        &self.strategy
+    }
+
+    /// Limits the number of GraphQL requests which are executed concurrently.
+    ///
+    /// The limit applies to the executions by the strategy configured so far, so the strategies
+    /// added after this method, such as [`response_cache`] and [`coalesce`], serve their
+    /// responses without taking a slot.  See [`Limited`] for details.
+    ///
+    /// [`response_cache`]: #method.response_cache
+    /// [`coalesce`]: #method.coalesce
+    /// [`Limited`]: ./struct.Limited.html
+    pub fn concurrency_limit(self, limit: ConcurrencyLimit) -> Custom<S, Limited<St>, A> {
+        self.map_strategy(|strategy| Limited::new(strategy, &limit))
+    }
+
+    /// Shares the result of identical GraphQL queries being executed concurrently.
+    ///
+    /// The specified function extracts a key from the context, and only the requests
+    /// with the same key are coalesced. It should distinguish all information in the
+    /// context which affects the results, such as the identity of the user.
+    /// See [`Coalesce`] for details.
+    ///
+    /// [`Coalesce`]: ./struct.Coalesce.html
+    pub fn coalesce<F, K>(self, key_fn: F) -> Custom<S, Coalesce<St, F, K>, A>
+    where
+        K: Hash + Eq,
+    {
+        self.map_strategy(|strategy| Coalesce::new(strategy, key_fn))
+    }
+
+    /// Serves the GraphQL queries from the specified response cache.
+    ///
+    /// The specified function derives the key from the context, which is a part of
+    /// the cache key. The requests are not cached if it returns `None`, so it can be
+    /// used for skipping the requests which contain private information.
+    /// See [`Cached`] for details.
+    ///
+    /// [`Cached`]: ./struct.Cached.html
+    pub fn response_cache<C, F>(self, cache: C, key_fn: F) -> Custom<S, Cached<St, C, F>, A>
+    where
+        C: ResponseCache,
+    {
+        self.map_strategy(|strategy| Cached::new(strategy, cache, key_fn))
+    }
+
+    /// Attaches the cache policy computed from the specified hints to the responses.
+    ///
+    /// See [`CacheControl`] for details.
+    ///
+    /// [`CacheControl`]: ./struct.CacheControl.html
+    pub fn cache_hints(self, hints: CacheHints) -> Custom<S, CacheControl<St>, A> {
+        self.map_strategy(|strategy| CacheControl::new(strategy, hints))
+    }
+
+    /// Checks the operations with the specified hook before executing them.
+    ///
+    /// The hook is called for each operation in the request along with the context,
+    /// and can allow, deny or rewrite it.  If any operation is denied, none of the operations
+    /// in the request are executed.  The requests whose operation cannot be determined,
+    /// e.g. due to a syntax error, are rejected with the status `400 Bad Request`
+    /// without calling the hook.
+    ///
+    /// The check is done before the execution strategy is invoked, so the results from
+    /// the response cache or the coalesced executions are never returned to the requests
+    /// rejected by the hook, regardless of the order of the builder methods.
+    /// Calling this method again replaces the hook.
+    pub fn authorize<F>(self, hook: F) -> Custom<S, St, F> {
+        Custom {
+            schema: self.schema,
+            strategy: self.strategy,
+            compression: self.compression,
+            request: self.request,
+            pretty: self.pretty,
+            tracing: self.tracing,
+            access_log: self.access_log,
+            slow_operation_log: self.slow_operation_log,
+            metrics: self.metrics,
+            field_usage: self.field_usage,
+            authorization: Some(hook),
+        }
+    }
+
+    /// Compresses the response bodies with the content coding negotiated with the client.
+    ///
+    /// See [`Compression`] for details.
+    ///
+    /// [`Compression`]: ../request/struct.Compression.html
+    pub fn compression(self, compression: Compression) -> Custom<S, St, A> {
+        Custom {
+            compression: Some(compression),
+            ..self
+        }
+    }
+
+    /// Rejects the requests which can be sent from other origins without a CORS preflight.
+    ///
+    /// See [`CsrfPrevention`] for details.
+    ///
+    /// [`CsrfPrevention`]: ../request/struct.CsrfPrevention.html
+    pub fn csrf_prevention(self, csrf_prevention: CsrfPrevention) -> Custom<S, St, A> {
+        Custom {
+            request: self.request.csrf_prevention(csrf_prevention),
+            ..self
+        }
+    }
+
+    /// Specifies whether to skip the requests with an unsupported method, instead of
+    /// rejecting them with `405 Method Not Allowed`.
+    ///
+    /// See [`GraphQLRequestEndpoint::skip_unsupported_methods`] for details.
+    ///
+    /// [`GraphQLRequestEndpoint::skip_unsupported_methods`]: ../request/struct.GraphQLRequestEndpoint.html#method.skip_unsupported_methods
+    pub fn skip_unsupported_methods(self, skip: bool) -> Custom<S, St, A> {
+        Custom {
+            request: self.request.skip_unsupported_methods(skip),
+            ..self
+        }
+    }
+
+    /// Specifies whether to pretty-print the bodies of all responses.
+    ///
+    /// In debug builds, the pretty-printed body can also be requested per request.
+    /// See [`GraphQLResponse::with_pretty`] for details.
+    ///
+    /// [`GraphQLResponse::with_pretty`]: ../request/struct.GraphQLResponse.html#method.with_pretty
+    pub fn pretty(self, pretty: bool) -> Custom<S, St, A> {
+        Custom { pretty, ..self }
+    }
+
+    /// Specifies whether to enable the tracing extension for the requests with the header
+    /// `X-Apollo-Tracing: 1`.
+    ///
+    /// It is disabled by default, since the traced requests bypass the response cache and
+    /// the coalescing of identical requests.
+    /// See [`GraphQLRequest::with_tracing`] for details.
+    ///
+    /// [`GraphQLRequest::with_tracing`]: ../request/struct.GraphQLRequest.html#method.with_tracing
+    pub fn tracing(self, tracing: bool) -> Custom<S, St, A> {
+        Custom { tracing, ..self }
+    }
+
+    /// Enables the access log of the executed operations with the specified configuration.
+    ///
+    /// See [`AccessLog`] for details.
+    ///
+    /// [`AccessLog`]: ./struct.AccessLog.html
+    pub fn access_log(self, access_log: AccessLog) -> Custom<S, St, A> {
+        Custom {
+            access_log: Some(access_log),
+            ..self
+        }
+    }
+
+    /// Reports the operations whose execution exceeds the threshold.
+    ///
+    /// See [`SlowOperationLog`] for details.
+    ///
+    /// [`SlowOperationLog`]: ./struct.SlowOperationLog.html
+    pub fn slow_operation_log(self, slow_operation_log: SlowOperationLog) -> Custom<S, St, A> {
+        Custom {
+            slow_operation_log: Some(Arc::new(slow_operation_log)),
+            ..self
+        }
+    }
+
+    /// Records the metrics of the GraphQL requests to the specified registry.
+    ///
+    /// See the module [`metrics`] for details.
+    ///
+    /// [`metrics`]: ../metrics/index.html
+    pub fn metrics(self, metrics: Arc<Metrics>) -> Custom<S, St, A> {
+        Custom {
+            metrics: Some(metrics),
+            ..self
+        }
+    }
+
+    /// Records the usage statistics of the fields to the specified registry.
+    ///
+    /// See the module [`usage`] for details.
+    ///
+    /// [`usage`]: ../usage/index.html
+    pub fn field_usage(self, field_usage: Arc<FieldUsage>) -> Custom<S, St, A> {
+        Custom {
+            field_usage: Some(field_usage),
+            ..self
+        }
+    }
+
+    // Replaces the execution strategy, keeping all other settings.
+    fn map_strategy<F, T>(self, f: F) -> Custom<S, T, A>
+    where
+        F: FnOnce(St) -> T,
+    {
+        Custom {
+            schema: self.schema,
+            strategy: f(self.strategy),
+            compression: self.compression,
+            request: self.request,
+            pretty: self.pretty,
+            tracing: self.tracing,
+            access_log: self.access_log,
+            slow_operation_log: self.slow_operation_log,
+            metrics: self.metrics,
+            field_usage: self.field_usage,
+            authorization: self.authorization,
+        }
+    }
+
+    fn into_endpoint_with<E>(self, context: E) -> CustomEndpoint<E, S, St, A> {
+        CustomEndpoint {
+            context,
+            request: self.request,
+            schema: Arc::new(self.schema),
+            strategy: self.strategy,
+            compression: self.compression,
+            pretty: self.pretty,
+            tracing: self.tracing,
+            access_log: self.access_log,
+            slow_operation_log: self.slow_operation_log,
+            metrics: self.metrics,
+            field_usage: self.field_usage,
+            authorization: self.authorization,
+        }
+    }
+}
+
+impl<'a, S, St, A> IntoEndpoint<'a> for Custom<S, St, A>
+where
+    S: 'a,
+    St: ExecutionStrategy<S, ()> + 'a,
+    St::Future: 'a,
+    A: AuthorizationHook<()> + 'a,
+{
+    type Output = (GraphQLResponse,);
+    type Endpoint = CustomEndpoint<endpoint::Cloned<()>, S, St, A>;
+
+    fn into_endpoint(self) -> Self::Endpoint {
+        self.into_endpoint_with(endpoint::cloned(()))
+    }
+}
+
+impl<'a, E, S, St, A, CtxT> Wrapper<'a, E> for Custom<S, St, A>
+where
+    E: Endpoint<'a, Output = (CtxT,)>,
+    S: 'a,
+    St: ExecutionStrategy<S, CtxT> + 'a,
+    St::Future: 'a,
+    A: AuthorizationHook<CtxT> + 'a,
+    CtxT: 'a,
+{
+    type Output = (GraphQLResponse,);
+    type Endpoint = CustomEndpoint<E, S, St, A>;
+
+    fn wrap(self, endpoint: E) -> Self::Endpoint {
+        self.into_endpoint_with(endpoint)
+    }
+}
+
+#[derive(Debug)]
+pub struct CustomEndpoint<E, S, St, A = ()> {
+    context: E,
+    request: GraphQLRequestEndpoint,
+    schema: Arc<S>,
+    strategy: St,
+    compression: Option<Compression>,
+    pretty: bool,
+    tracing: bool,
+    access_log: Option<AccessLog>,
+    slow_operation_log: Option<Arc<SlowOperationLog>>,
+    metrics: Option<Arc<Metrics>>,
+    field_usage: Option<Arc<FieldUsage>>,
+    authorization: Option<A>,
+}
+
+impl<'a, E, S, St, A, CtxT> Endpoint<'a> for CustomEndpoint<E, S, St, A>
+where
+    E: Endpoint<'a, Output = (CtxT,)>,
+    S: 'a,
+    St: ExecutionStrategy<S, CtxT> + 'a,
+    St::Future: 'a,
+    A: AuthorizationHook<CtxT> + 'a,
+    CtxT: 'a,
+{
+    type Output = (GraphQLResponse,);
+    type Future = CustomFuture<'a, E, S, St, CtxT, A>;
+
+    fn apply(&'a self, cx: &mut ApplyContext<'_>) -> ApplyResult<Self::Future> {
+        let rejected = |err: ApplyError| {
+            // `404` means that the request is not routed to this endpoint.
+            if err.status_code() != StatusCode::NOT_FOUND {
+                self.observe_rejection(err.status_code());
+            }
+            err
+        };
+        // The request is checked before the context, so that the unsupported methods are
+        // reported as `405` rather than e.g. `401` from the context.
+        let request = self.request.apply(cx).map_err(rejected)?;
+        if let Some(err) = request.rejection() {
+            return Ok(CustomFuture {
+                state: State::Rejected(Some(err)),
+                endpoint: self,
+                request_id: None,
+            });
+        }
+        let context = self.context.apply(cx).map_err(rejected)?;
+        Ok(CustomFuture {
+            state: State::Receiving(context.join(request)),
+            endpoint: self,
+            request_id: RequestId::from_input(cx.input()),
+        })
+    }
+}
+
+impl<E, S, St, A> CustomEndpoint<E, S, St, A> {
+    fn observe_rejection(&self, status: StatusCode) {
+        if let Some(ref metrics) = self.metrics {
+            metrics.observe_rejection(status);
+        }
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct CustomFuture<'a, E, S, St, CtxT, A = ()>
+where
+    E: Endpoint<'a, Output = (CtxT,)>,
+    St: ExecutionStrategy<S, CtxT>,
+{
+    state: State<'a, E, St::Future>,
+    endpoint: &'a CustomEndpoint<E, S, St, A>,
+    request_id: Option<RequestId>,
+}
+
+enum State<'a, E: Endpoint<'a>, F> {
+    Rejected(Option<Error>),
+    Receiving(future::Join<E::Future, RequestFuture<'a>>),
+    // The in-flight request is removed from the metrics when the execution completes
+    // or this future is dropped.
+    Executing(F, Option<InFlight>),
+}
+
+impl<'a, E, S, St, CtxT, A> CustomFuture<'a, E, S, St, CtxT, A>
+where
+    E: Endpoint<'a, Output = (CtxT,)>,
+    St: ExecutionStrategy<S, CtxT>,
+{
+    fn finish(&self, mut response: GraphQLResponse) -> GraphQLResponse {
+        if let Some(compression) = self.endpoint.compression {
+            response = response.with_compression(compression);
+        }
+        if self.endpoint.pretty {
+            response = response.with_pretty(true);
+        }
+        if let Some(ref request_id) = self.request_id {
+            response.set_extension("requestId", json!(request_id.as_str()));
+        }
+        response
+    }
+}
+
+impl<'a, E, S, St, CtxT, A> Future for CustomFuture<'a, E, S, St, CtxT, A>
+where
+    E: Endpoint<'a, Output = (CtxT,)>,
+    St: ExecutionStrategy<S, CtxT>,
+    A: AuthorizationHook<CtxT>,
+{
+    type Item = (GraphQLResponse,);
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let endpoint = self.endpoint;
+        loop {
+            self.state = match self.state {
+                State::Rejected(ref mut err) => {
+                    let err = err.take().expect("the future has already polled");
+                    endpoint.observe_rejection(err.status_code());
+                    return Err(err);
+                }
+                State::Receiving(ref mut inner) => {
+                    let ((context,), (mut request,)) = match inner.poll() {
+                        Ok(Async::Ready(output)) => output,
+                        Ok(Async::NotReady) => return Ok(Async::NotReady),
+                        // The missing credentials are reported in the GraphQL response.
+                        Err(err) => match err.downcast_ref::<Unauthorized>() {
+                            Some(err) => {
+                                if let Some(ref metrics) = endpoint.metrics {
+                                    metrics.observe_error("UNAUTHENTICATED");
+                                }
+                                return Ok((self.finish(err.to_response()),).into());
+                            }
+                            None => {
+                                endpoint.observe_rejection(err.status_code());
+                                return Err(err);
+                            }
+                        },
+                    };
+                    // The authorization is checked before the execution strategy, which may
+                    // serve the response without executing the request.
+                    if let Some(ref hook) = endpoint.authorization {
+                        if let Some(response) = authorize::check(hook, &mut request, &context) {
+                            endpoint.observe_rejection(response.status());
+                            return Ok((self.finish(response),).into());
+                        }
+                    }
+                    if endpoint.tracing && request.is_tracing_requested() {
+                        request = request.with_tracing(true);
+                    }
+                    if let Some(access_log) = endpoint.access_log {
+                        request = request.with_access_log(access_log);
+                    }
+                    if let Some(ref slow_operation_log) = endpoint.slow_operation_log {
+                        request = request.with_slow_operation_log(slow_operation_log.clone());
+                    }
+                    if let Some(ref metrics) = endpoint.metrics {
+                        metrics.observe_batch_size(request.requests().len());
+                        request = request.with_metrics(metrics.clone());
+                    }
+                    if let Some(ref field_usage) = endpoint.field_usage {
+                        request = request.with_field_usage(field_usage.clone());
+                    }
+                    if let Some(ref request_id) = self.request_id {
+                        request = request.with_request_id(request_id.clone());
+                    }
+                    State::Executing(
+                        endpoint
+                            .strategy
+                            .execute(request, context, endpoint.schema.clone()),
+                        endpoint.metrics.as_ref().map(Metrics::start),
+                    )
+                }
+                State::Executing(ref mut future, ref mut in_flight) => {
+                    let response = match future.poll() {
+                        Ok(Async::Ready(response)) => response,
+                        Ok(Async::NotReady) => return Ok(Async::NotReady),
+                        Err(err) => {
+                            endpoint.observe_rejection(err.status_code());
+                            return Err(err);
+                        }
+                    };
+                    drop(in_flight.take());
+                    if let Some(ref metrics) = endpoint.metrics {
+                        if response.status() == StatusCode::INTERNAL_SERVER_ERROR {
+                            metrics.observe_error("INTERNAL_SERVER_ERROR");
+                        }
+                    }
+                    return Ok((self.finish(response),).into());
+                }
+            };
+        }
+    }
+}
@@ -0,0 +1,430 @@
+//! Concurrency limit for the spawning executors.
+
+use finchers::error::{Error, HttpError};
+
+use futures::task::{self, Task};
+use futures::{Async, Future, Poll};
+
+use http::header::{HeaderMap, HeaderValue};
+use http::{header, StatusCode};
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use super::custom::ExecutionStrategy;
+use request::{GraphQLRequest, GraphQLResponse};
+
+/// The configuration of the concurrency limit applied to the spawned GraphQL tasks.
+///
+/// At most `max_concurrency` tasks are executed at the same time, and at most
+/// `max_queue_length` requests wait for a free slot.  When the queue is full,
+/// the executor responds immediately with `503 Service Unavailable` and a `Retry-After` header.
+#[derive(Debug, Clone)]
+pub struct ConcurrencyLimit {
+    max_concurrency: usize,
+    max_queue_length: usize,
+    retry_after: Duration,
+}
+
+impl ConcurrencyLimit {
+    /// Creates a new `ConcurrencyLimit` which allows at most `max_concurrency` tasks
+    /// to be executed concurrently.
+    ///
+    /// By default, the length of the waiting queue is the same as `max_concurrency`
+    /// and the value of `Retry-After` header is 1 second.
+    pub fn new(max_concurrency: usize) -> ConcurrencyLimit {
+        assert!(max_concurrency > 0, "max_concurrency must be positive");
+        ConcurrencyLimit {
+            max_concurrency,
+            max_queue_length: max_concurrency,
+            retry_after: Duration::from_secs(1),
+        }
+    }
+
+    /// Sets the maximum number of the requests waiting for a free slot.
+    pub fn max_queue_length(self, max_queue_length: usize) -> ConcurrencyLimit {
+        ConcurrencyLimit {
+            max_queue_length,
+            ..self
+        }
+    }
+
+    /// Sets the value of `Retry-After` header returned when the queue is full.
+    ///
+    /// The duration is rounded up to seconds, and at least 1 second is sent.
+    pub fn retry_after(self, retry_after: Duration) -> ConcurrencyLimit {
+        ConcurrencyLimit {
+            retry_after,
+            ..self
+        }
+    }
+
+    pub(crate) fn build(&self) -> Semaphore {
+        Semaphore {
+            inner: Arc::new(Mutex::new(State {
+                permits: self.max_concurrency,
+                waiters: VecDeque::new(),
+                next_id: 0,
+            })),
+            max_queue_length: self.max_queue_length,
+            retry_after: self.retry_after,
+        }
+    }
+}
+
+// ==== Limited ====
+
+/// An execution strategy which limits the number of GraphQL requests executed concurrently
+/// by the underlying strategy.
+///
+/// A slot is held until the execution by the underlying strategy completes, even if the client
+/// goes away in the meantime, so that the abandoned tasks are also counted.  The requests
+/// cancelled before starting the execution release their slots without running.
+///
+/// The value of this type is created by [`Custom::concurrency_limit`].
+///
+/// [`Custom::concurrency_limit`]: ./struct.Custom.html#method.concurrency_limit
+#[derive(Debug)]
+pub struct Limited<St> {
+    inner: Arc<St>,
+    semaphore: Semaphore,
+}
+
+impl<St> Limited<St> {
+    pub(crate) fn new(inner: St, limit: &ConcurrencyLimit) -> Limited<St> {
+        Limited {
+            inner: Arc::new(inner),
+            semaphore: limit.build(),
+        }
+    }
+
+    /// Returns a reference to the underlying execution strategy.
+    pub fn inner(&self) -> &St {
+        &self.inner
+    }
+}
+
+impl<S, CtxT, St> ExecutionStrategy<S, CtxT> for Limited<St>
+where
+    St: ExecutionStrategy<S, CtxT>,
+{
+    type Future = LimitedFuture<S, CtxT, St>;
+
+    fn execute(&self, request: GraphQLRequest, context: CtxT, schema: Arc<S>) -> Self::Future {
+        LimitedFuture {
+            acquire: self.semaphore.acquire(),
+            inner: self.inner.clone(),
+            args: Some((request, context, schema)),
+            future: None,
+        }
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct LimitedFuture<S, CtxT, St: ExecutionStrategy<S, CtxT>> {
+    acquire: Acquire,
+    inner: Arc<St>,
+    args: Option<(GraphQLRequest, CtxT, Arc<S>)>,
+    future: Option<St::Future>,
+}
+
+impl<S, CtxT, St> Future for LimitedFuture<S, CtxT, St>
+where
+    St: ExecutionStrategy<S, CtxT>,
+{
+    type Item = GraphQLResponse;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        if self.future.is_none() {
+            let permit = try_ready!(self.acquire.poll());
+            let (request, context, schema) =
+                self.args.take().expect("the future has already polled");
+            // The permit is moved into the task along with the request, so that
+            // it is not released until the task actually finishes.
+            self.future = Some(
+                self.inner
+                    .execute(request.with_permit(permit), context, schema),
+            );
+        }
+        self.future
+            .as_mut()
+            .expect("the future has already polled")
+            .poll()
+    }
+}
+
+// ==== Semaphore ====
+
+#[derive(Debug, Clone)]
+pub(crate) struct Semaphore {
+    inner: Arc<Mutex<State>>,
+    max_queue_length: usize,
+    retry_after: Duration,
+}
+
+#[derive(Debug)]
+struct State {
+    permits: usize,
+    waiters: VecDeque<(usize, Task)>,
+    next_id: usize,
+}
+
+impl State {
+    fn notify_head(&self) {
+        if self.permits > 0 {
+            if let Some((_, task)) = self.waiters.front() {
+                task.notify();
+            }
+        }
+    }
+}
+
+impl Semaphore {
+    pub(crate) fn acquire(&self) -> Acquire {
+        Acquire {
+            semaphore: self.clone(),
+            id: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Acquire {
+    semaphore: Semaphore,
+    id: Option<usize>,
+}
+
+impl Future for Acquire {
+    type Item = Permit;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let mut state = self.semaphore.inner.lock().unwrap();
+        match self.id {
+            None if state.permits > 0 && state.waiters.is_empty() => {}
+            None if state.waiters.len() < self.semaphore.max_queue_length => {
+                let id = state.next_id;
+                state.next_id = state.next_id.wrapping_add(1);
+                state.waiters.push_back((id, task::current()));
+                self.id = Some(id);
+                trace!(
+                    "the GraphQL task is queued (queue length = {})",
+                    state.waiters.len()
+                );
+                return Ok(Async::NotReady);
+            }
+            None => {
+                return Err(QueueFull {
+                    retry_after: self.semaphore.retry_after,
+                }
+                .into())
+            }
+            Some(id) => {
+                let is_head = state.waiters.front().map(|w| w.0) == Some(id);
+                if !(is_head && state.permits > 0) {
+                    if let Some(waiter) = state.waiters.iter_mut().find(|w| w.0 == id) {
+                        waiter.1 = task::current();
+                    }
+                    return Ok(Async::NotReady);
+                }
+                state.waiters.pop_front();
+                self.id = None;
+            }
+        }
+
+        state.permits -= 1;
+        state.notify_head();
+        Ok(Async::Ready(Permit {
+            inner: self.semaphore.inner.clone(),
+        }))
+    }
+}
+
+impl Drop for Acquire {
+    fn drop(&mut self) {
+        if let Some(id) = self.id.take() {
+            let mut state = self.semaphore.inner.lock().unwrap();
+            state.waiters.retain(|&(waiter, _)| waiter != id);
+            state.notify_head();
+        }
+    }
+}
+
+/// A permit for executing a GraphQL task, which is released when dropped.
+pub(crate) struct Permit {
+    inner: Arc<Mutex<State>>,
+}
+
+impl fmt::Debug for Permit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Permit").finish()
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let mut state = self.inner.lock().unwrap();
+        state.permits += 1;
+        state.notify_head();
+    }
+}
+
+// ==== QueueFull ====
+
+#[derive(Debug)]
+struct QueueFull {
+    retry_after: Duration,
+}
+
+impl fmt::Display for QueueFull {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("too many GraphQL requests are waiting for execution")
+    }
+}
+
+impl HttpError for QueueFull {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::SERVICE_UNAVAILABLE
+    }
+
+    fn headers(&self, headers: &mut HeaderMap) {
+        // Rounded up, so that the clients do not retry immediately.
+        let mut secs = self.retry_after.as_secs();
+        if self.retry_after.subsec_nanos() > 0 || secs == 0 {
+            secs += 1;
+        }
+        headers.insert(
+            header::RETRY_AFTER,
+            HeaderValue::from_str(&secs.to_string()).expect("should be a valid header value"),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use finchers::endpoint;
+    use finchers::prelude::*;
+    use finchers::test;
+    use futures::{future, Async, Future};
+    use http::{header, Request, StatusCode};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::ConcurrencyLimit;
+    use execute::{thread_pool, InMemoryCache, ThreadPoolConfig};
+    use testing::{probe_schema, Abandon, Probe};
+
+    #[test]
+    fn test_queue_full() {
+        let semaphore = ConcurrencyLimit::new(1)
+            .max_queue_length(1)
+            .retry_after(Duration::from_secs(3))
+            .build();
+
+        future::lazy(|| {
+            let permit = semaphore.acquire().poll().unwrap();
+            assert_matches!(permit, Async::Ready(..));
+
+            let mut waiting = semaphore.acquire();
+            assert_matches!(waiting.poll(), Ok(Async::NotReady));
+
+            let err = semaphore.acquire().poll().unwrap_err();
+            assert_eq!(err.status_code(), StatusCode::SERVICE_UNAVAILABLE);
+            let mut headers = Default::default();
+            err.headers(&mut headers);
+            assert_eq!(headers[header::RETRY_AFTER], "3");
+
+            drop(permit);
+            assert_matches!(waiting.poll(), Ok(Async::Ready(..)));
+            Ok::<(), ()>(())
+        })
+        .wait()
+        .unwrap();
+    }
+
+    #[test]
+    fn test_abandoned_task_holds_permit() {
+        let probe = Probe::new();
+        let executor = thread_pool(probe_schema(), ThreadPoolConfig::new().pool_size(1));
+        let pool = executor.handle();
+        let executor = executor.concurrency_limit(ConcurrencyLimit::new(1).max_queue_length(0));
+        let endpoint = Arc::new(endpoint::cloned(probe.clone()).wrap(executor));
+        let mut runner = test::runner(endpoint.clone());
+        let mut abandoning = test::runner(Abandon::new(endpoint, {
+            let probe = probe.clone();
+            move || probe.wait_started(1)
+        }));
+
+        assert!(!abandoning.apply(Request::get("/?query={block}")).unwrap());
+
+        // The resolver ignoring the cancellation is still running, so the slot is not available.
+        let response = runner.perform(Request::get("/?query={ready}")).unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "1");
+
+        probe.release();
+        pool.shutdown().wait().unwrap();
+        assert_eq!(probe.cancelled(), 0);
+        assert_eq!(pool.worker_stats()[0].completed_tasks(), 1);
+    }
+
+    #[test]
+    fn test_retry_after_rounded_up() {
+        for &(retry_after, expected) in &[
+            (Duration::from_millis(0), "1"),
+            (Duration::from_millis(200), "1"),
+            (Duration::from_millis(1500), "2"),
+            (Duration::from_secs(2), "2"),
+        ] {
+            let semaphore = ConcurrencyLimit::new(1)
+                .max_queue_length(0)
+                .retry_after(retry_after)
+                .build();
+            future::lazy(|| {
+                let _permit = semaphore.acquire().poll().unwrap();
+                let err = semaphore.acquire().poll().unwrap_err();
+                let mut headers = Default::default();
+                err.headers(&mut headers);
+                assert_eq!(headers[header::RETRY_AFTER], expected);
+                Ok::<(), ()>(())
+            })
+            .wait()
+            .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_cache_hit_without_slot() {
+        let probe = Probe::new();
+        let executor = thread_pool(probe_schema(), ThreadPoolConfig::new().pool_size(1));
+        let pool = executor.handle();
+        let executor = executor
+            .concurrency_limit(ConcurrencyLimit::new(1).max_queue_length(0))
+            .response_cache(
+                InMemoryCache::new(16, Duration::from_secs(3600)),
+                |_: &Probe| Some("".into()),
+            );
+        let endpoint = Arc::new(endpoint::cloned(probe.clone()).wrap(executor));
+        let mut runner = test::runner(endpoint.clone());
+        let mut abandoning = test::runner(Abandon::new(endpoint, {
+            let probe = probe.clone();
+            move || probe.wait_started(1)
+        }));
+
+        let response = runner.perform(Request::get("/?query={ready}")).unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        assert!(!abandoning.apply(Request::get("/?query={block}")).unwrap());
+
+        // The cached response is served while the only slot is held by the running task.
+        let response = runner.perform(Request::get("/?query={ready}")).unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = runner.perform(Request::get("/?query={ready%20}")).unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        probe.release();
+        pool.shutdown().wait().unwrap();
+    }
+}
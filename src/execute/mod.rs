@@ -16,17 +16,57 @@
 //!   task executor. Unlike to `Nonblocking`, it does not notify the start of
 //!   blocking section to the runtime.
 //!
-//! [`Nonblocking`]: ./struct.Nonblocking.html
-//! [`CurrentThread`]: ./struct.CurrentThread.html
-//! [`WithSpawner`]: ./struct.WithSpawner.html
-
+//! * [`ThreadPool`]
+//!   It sends the task for executing GraphQL queries to a dedicated worker pool
+//!   owned by the executor.
+//!
+//! All of them are the combination of [`Custom`] and an execution strategy.
+//! A user-defined strategy can be plugged by implementing [`ExecutionStrategy`]
+//! and passing it to [`custom`].
+//!
+//! [`Nonblocking`]: ./type.Nonblocking.html
+//! [`CurrentThread`]: ./type.CurrentThread.html
+//! [`WithSpawner`]: ./type.WithSpawner.html
+//! [`ThreadPool`]: ./type.ThreadPool.html
+//! [`Custom`]: ./struct.Custom.html
+//! [`ExecutionStrategy`]: ./trait.ExecutionStrategy.html
+//! [`custom`]: ./fn.custom.html
+
+mod access_log;
+mod authorize;
+mod cache;
+mod cache_control;
+pub mod cancel;
+mod coalesce;
 mod current_thread;
+mod custom;
+pub(crate) mod limit;
 mod nonblocking;
+mod panic;
+mod slow_log;
+mod task;
+mod thread_pool;
+pub(crate) mod tracing;
 mod with_spawner;
 
-pub use self::current_thread::{current_thread, CurrentThread};
-pub use self::nonblocking::{nonblocking, Nonblocking};
-pub use self::with_spawner::{with_spawner, WithSpawner};
+pub use self::access_log::AccessLog;
+pub use self::authorize::{Authorization, AuthorizationHook, OperationInfo, OperationType};
+pub use self::cache::{CacheKey, Cached, InMemoryCache, ResponseCache};
+pub use self::cache_control::{CacheControl, CacheHint, CacheHints, CacheScope};
+pub use self::cancel::CancellationToken;
+pub use self::coalesce::Coalesce;
+pub use self::current_thread::{current_thread, CurrentThread, CurrentThreadStrategy};
+pub use self::custom::{custom, Custom, ExecutionStrategy};
+pub use self::limit::{ConcurrencyLimit, Limited};
+pub use self::nonblocking::{nonblocking, Nonblocking, NonblockingStrategy};
+pub use self::slow_log::{SlowOperation, SlowOperationLog};
+pub use self::task::GraphQLTask;
+pub use self::thread_pool::{
+    thread_pool, Shutdown, ThreadPool, ThreadPoolConfig, ThreadPoolHandle, ThreadPoolStrategy,
+    WorkerStats,
+};
+pub use self::tracing::Traced;
+pub use self::with_spawner::{with_spawner, WithSpawner, WithSpawnerStrategy};
 
 pub use self::schema::Schema;
 pub use self::shared::SharedSchema;
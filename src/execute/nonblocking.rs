@@ -1,15 +1,15 @@
-use finchers::endpoint;
-use finchers::endpoint::wrapper::Wrapper;
-use finchers::endpoint::{ApplyContext, ApplyResult, Endpoint, IntoEndpoint};
+use finchers::error;
 use finchers::error::Error;
 use finchers::rt;
 
-use futures::future;
 use futures::{Future, Poll};
 use std::sync::Arc;
 
+use super::cancel::{cancelled, CancelOnDrop};
+use super::custom::{custom, Custom, ExecutionStrategy};
 use super::shared::SharedSchema;
-use request::{GraphQLRequestEndpoint, GraphQLResponse, RequestFuture};
+use super::task::GraphQLTask;
+use request::{GraphQLRequest, GraphQLResponse};
 
 /// Create a GraphQL executor from the specified `RootNode`.
 ///
@@ -20,104 +20,60 @@ pub fn nonblocking<S>(schema: S) -> Nonblocking<S>
 where
     S: SharedSchema,
 {
-    Nonblocking { schema }
+    custom(schema, NonblockingStrategy { _priv: () })
 }
 
-#[allow(missing_docs)]
-#[derive(Debug)]
-pub struct Nonblocking<S> {
-    schema: S,
-}
+/// A GraphQL executor which spawns the tasks by using the Tokio's default executor.
+pub type Nonblocking<S> = Custom<S, NonblockingStrategy>;
 
-impl<'a, S> IntoEndpoint<'a> for Nonblocking<S>
-where
-    S: SharedSchema<Context = ()>,
-{
-    type Output = (GraphQLResponse,);
-    type Endpoint = NonblockingEndpoint<endpoint::Cloned<()>, S>;
-
-    fn into_endpoint(self) -> Self::Endpoint {
-        NonblockingEndpoint {
-            context: endpoint::cloned(()),
-            request: ::request::graphql_request(),
-            schema: Arc::new(self.schema),
-        }
-    }
+/// The execution strategy used in [`Nonblocking`].
+///
+/// [`Nonblocking`]: ./type.Nonblocking.html
+#[derive(Debug)]
+pub struct NonblockingStrategy {
+    _priv: (),
 }
 
-impl<'a, E, S> Wrapper<'a, E> for Nonblocking<S>
+impl<S> ExecutionStrategy<S, S::Context> for NonblockingStrategy
 where
-    E: Endpoint<'a, Output = (S::Context,)>,
     S: SharedSchema,
 {
-    type Output = (GraphQLResponse,);
-    type Endpoint = NonblockingEndpoint<E, S>;
+    type Future = NonblockingFuture;
 
-    fn wrap(self, endpoint: E) -> Self::Endpoint {
-        NonblockingEndpoint {
-            context: endpoint,
-            request: ::request::graphql_request(),
-            schema: Arc::new(self.schema),
+    fn execute(
+        &self,
+        request: GraphQLRequest,
+        context: S::Context,
+        schema: Arc<S>,
+    ) -> Self::Future {
+        trace!("spawn a GraphQL task using the default executor");
+        let task = GraphQLTask::new(request, schema, context);
+        let guard = CancelOnDrop(task.cancellation_token().clone());
+        let future = rt::blocking_section(move || -> error::Result<_> {
+            if task.is_cancelled() {
+                return Err(cancelled());
+            }
+            Ok(task.execute())
+        });
+        NonblockingFuture {
+            handle: rt::spawn_with_handle(future),
+            _guard: guard,
         }
     }
 }
 
+// The guard cancels the spawned task when this future is dropped before completion.
 #[derive(Debug)]
-pub struct NonblockingEndpoint<E, S> {
-    context: E,
-    request: GraphQLRequestEndpoint,
-    schema: Arc<S>,
+pub struct NonblockingFuture {
+    handle: rt::SpawnHandle<GraphQLResponse, Error>,
+    _guard: CancelOnDrop,
 }
 
-impl<'a, E, S> Endpoint<'a> for NonblockingEndpoint<E, S>
-where
-    E: Endpoint<'a, Output = (S::Context,)>,
-    S: SharedSchema,
-{
-    type Output = (GraphQLResponse,);
-    type Future = NonblockingFuture<'a, E, S>;
-
-    fn apply(&'a self, cx: &mut ApplyContext<'_>) -> ApplyResult<Self::Future> {
-        let context = self.context.apply(cx)?;
-        let request = self.request.apply(cx)?;
-        Ok(NonblockingFuture {
-            inner: context.join(request),
-            handle: None,
-            endpoint: self,
-        })
-    }
-}
-
-#[allow(missing_debug_implementations)]
-pub struct NonblockingFuture<'a, E: Endpoint<'a>, S: 'a> {
-    inner: future::Join<E::Future, RequestFuture<'a>>,
-    handle: Option<rt::SpawnHandle<GraphQLResponse, Error>>,
-    endpoint: &'a NonblockingEndpoint<E, S>,
-}
-
-impl<'a, E, S> Future for NonblockingFuture<'a, E, S>
-where
-    E: Endpoint<'a, Output = (S::Context,)>,
-    S: SharedSchema,
-{
-    type Item = (GraphQLResponse,);
+impl Future for NonblockingFuture {
+    type Item = GraphQLResponse;
     type Error = Error;
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        loop {
-            match self.handle {
-                Some(ref mut handle) => return handle.poll().map(|x| x.map(|response| (response,))),
-                None => {
-                    let ((context,), (request,)) = try_ready!(self.inner.poll());
-
-                    trace!("spawn a GraphQL task using the default executor");
-                    let schema = self.endpoint.schema.clone();
-                    let future = rt::blocking_section(move || -> ::finchers::error::Result<_> {
-                        Ok(request.execute(schema.as_root_node(), &context))
-                    });
-                    self.handle = Some(rt::spawn_with_handle(future));
-                }
-            }
-        }
+        self.handle.poll()
     }
 }
@@ -0,0 +1,113 @@
+//! Isolation of panics occurred in the resolvers.
+
+use backtrace::Backtrace;
+use std::any::Any;
+use std::env;
+use std::panic;
+use std::panic::AssertUnwindSafe;
+
+use request::GraphQLResponse;
+
+/// Executes a GraphQL request by using the specified closure and converts the panic
+/// occurred in it into a response with `500 Internal Server Error`.
+///
+/// The context and schema are shared only with the panicked request and then
+/// discarded by the executors, so it is safe to assert the unwind safety here.
+/// The panic hook is left untouched, so the panic is also reported by the hook
+/// installed by the application (or the default one).  If `RUST_BACKTRACE` is set,
+/// the backtrace captured where the panic is caught is logged along with the message.
+pub(crate) fn catch_unwind(f: impl FnOnce() -> GraphQLResponse) -> GraphQLResponse {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        let backtrace = if is_backtrace_enabled() {
+            Some(Backtrace::new())
+        } else {
+            None
+        };
+        error!("{}", panic_report(&*payload, backtrace.as_ref()));
+        GraphQLResponse::internal_server_error()
+    })
+}
+
+fn is_backtrace_enabled() -> bool {
+    env::var("RUST_BACKTRACE")
+        .map(|value| value != "0")
+        .unwrap_or(false)
+}
+
+fn panic_report(payload: &(dyn Any + Send), backtrace: Option<&Backtrace>) -> String {
+    let message = format!(
+        "a panic occurred during executing the GraphQL request: {}",
+        panic_message(payload),
+    );
+    match backtrace {
+        Some(backtrace) => format!("{}\n{:?}", message, backtrace),
+        None => message,
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(s) = payload.downcast_ref::<&'static str>() {
+        s
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s
+    } else {
+        "Box<Any>"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use finchers::prelude::*;
+    use finchers::test;
+    use futures_cpupool::CpuPool;
+    use http::{Request, StatusCode};
+    use serde_json::Value;
+
+    use backtrace::Backtrace;
+
+    use super::{catch_unwind, panic_report};
+    use execute::with_spawner;
+    use testing::{json_body, probe_schema, Probe};
+
+    #[test]
+    fn test_catch_unwind() {
+        let mut runner =
+            test::runner(endpoint::unit().map(|| catch_unwind(|| panic!("resolver panicked"))));
+        let response = runner.perform(Request::get("/")).unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let body = json_body(&response);
+        assert_eq!(body["data"], Value::Null);
+        assert_eq!(
+            body["errors"][0]["extensions"]["code"],
+            "INTERNAL_SERVER_ERROR"
+        );
+    }
+
+    #[test]
+    fn test_panic_report() {
+        let report = panic_report(&"resolver panicked", None);
+        assert_eq!(
+            report,
+            "a panic occurred during executing the GraphQL request: resolver panicked"
+        );
+
+        let report = panic_report(&String::from("resolver panicked"), Some(&Backtrace::new()));
+        assert!(report.starts_with(
+            "a panic occurred during executing the GraphQL request: resolver panicked\n"
+        ));
+        assert!(report.contains("test_panic_report"));
+    }
+
+    #[test]
+    fn test_panic_in_spawned_task() {
+        let endpoint =
+            endpoint::cloned(Probe::new()).wrap(with_spawner(probe_schema(), CpuPool::new(1)));
+        let mut runner = test::runner(endpoint);
+        let response = runner.perform(Request::get("/?query={panic}")).unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        // the worker is still available after the panic.
+        let response = runner.perform(Request::get("/?query={ready}")).unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,195 @@
+use serde_json;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use request::SingleRequest;
+use request_id::RequestId;
+
+/// The target of the log records of slow operations.
+const TARGET: &str = "finchers_juniper::slow_operation";
+
+type Reporter = Box<dyn Fn(&SlowOperation<'_>) + Send + Sync + 'static>;
+
+/// A reporter of the GraphQL operations whose execution exceeds a threshold.
+///
+/// By default, the slow operations are logged at the `WARN` level with the target
+/// `finchers_juniper::slow_operation`. The same operation (i.e. the pair of the normalized
+/// document and the operation name) is reported at most once per interval, so that
+/// a frequently executed slow operation does not flood the logs.
+pub struct SlowOperationLog {
+    threshold: Duration,
+    interval: Duration,
+    reporter: Option<Reporter>,
+    last_reported: Mutex<HashMap<u64, Instant>>,
+}
+
+impl fmt::Debug for SlowOperationLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SlowOperationLog")
+            .field("threshold", &self.threshold)
+            .field("interval", &self.interval)
+            .finish()
+    }
+}
+
+impl SlowOperationLog {
+    /// Creates a new `SlowOperationLog` with the specified threshold.
+    ///
+    /// The default interval of the reports of the same operation is 60 seconds.
+    pub fn new(threshold: Duration) -> SlowOperationLog {
+        SlowOperationLog {
+            threshold,
+            interval: Duration::from_secs(60),
+            reporter: None,
+            last_reported: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Sets the minimum interval between the reports of the same operation.
+    pub fn interval(self, interval: Duration) -> SlowOperationLog {
+        SlowOperationLog { interval, ..self }
+    }
+
+    /// Sets the function called for each slow operation, instead of logging it.
+    pub fn report_with<F>(self, f: F) -> SlowOperationLog
+    where
+        F: Fn(&SlowOperation<'_>) + Send + Sync + 'static,
+    {
+        SlowOperationLog {
+            reporter: Some(Box::new(f)),
+            ..self
+        }
+    }
+
+    pub(crate) fn observe(
+        &self,
+        request: &SingleRequest,
+        duration: Duration,
+        request_id: Option<&RequestId>,
+    ) {
+        if duration < self.threshold {
+            return;
+        }
+
+        let document = request.document();
+        let operation_name = request.operation_name().or_else(|| {
+            document
+                .and_then(|document| document.operation(None))
+                .and_then(|operation| operation.name())
+        });
+        let operation = SlowOperation {
+            document: document.map_or(request.query(), |document| document.normalized()),
+            operation_name,
+            duration,
+            request_id,
+        };
+
+        let mut hasher = DefaultHasher::new();
+        operation.document.hash(&mut hasher);
+        operation.operation_name.hash(&mut hasher);
+        if !self.acquire(hasher.finish()) {
+            return;
+        }
+
+        match self.reporter {
+            Some(ref reporter) => reporter(&operation),
+            None => warn!(
+                target: TARGET,
+                "slow GraphQL operation: operation_name={} duration_ms={} request_id={} document={}",
+                operation.operation_name.unwrap_or("-"),
+                duration.as_secs() * 1000 + u64::from(duration.subsec_millis()),
+                request_id.map_or("-", RequestId::as_str),
+                serde_json::to_string(operation.document).unwrap_or_default(),
+            ),
+        }
+    }
+
+    // Returns `true` if the operation with the specified key can be reported now.
+    fn acquire(&self, key: u64) -> bool {
+        let now = Instant::now();
+        let interval = self.interval;
+        let mut last_reported = self.last_reported.lock().unwrap();
+        if last_reported
+            .get(&key)
+            .map_or(false, |&last| now.duration_since(last) < interval)
+        {
+            return false;
+        }
+        last_reported.retain(|_, &mut last| now.duration_since(last) < interval);
+        last_reported.insert(key, now);
+        true
+    }
+}
+
+/// The information about a slow GraphQL operation.
+#[derive(Debug)]
+pub struct SlowOperation<'a> {
+    document: &'a str,
+    operation_name: Option<&'a str>,
+    duration: Duration,
+    request_id: Option<&'a RequestId>,
+}
+
+impl<'a> SlowOperation<'a> {
+    /// Returns the normalized document which contains the operation.
+    pub fn document(&self) -> &'a str {
+        self.document
+    }
+
+    /// Returns the name of the operation, if available.
+    pub fn operation_name(&self) -> Option<&'a str> {
+        self.operation_name
+    }
+
+    /// Returns the duration of the execution.
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// Returns the identifier of the request, if available.
+    pub fn request_id(&self) -> Option<&'a RequestId> {
+        self.request_id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use super::SlowOperationLog;
+    use request::GraphQLRequest;
+
+    #[test]
+    fn test_slow_operation_log() {
+        let reports = Arc::new(Mutex::new(vec![]));
+        let slow_log = SlowOperationLog::new(Duration::from_millis(100)).report_with({
+            let reports = reports.clone();
+            move |operation| {
+                reports.lock().unwrap().push((
+                    operation.document().to_owned(),
+                    operation.operation_name().map(ToOwned::to_owned),
+                ))
+            }
+        });
+        let request = GraphQLRequest::single("query Hero {\n  hero { name }\n}".into(), None, None);
+        let request = &request.requests()[0];
+
+        slow_log.observe(request, Duration::from_millis(10), None);
+        assert!(reports.lock().unwrap().is_empty());
+
+        slow_log.observe(request, Duration::from_millis(200), None);
+        slow_log.observe(request, Duration::from_millis(300), None);
+        assert_eq!(
+            *reports.lock().unwrap(),
+            vec![(
+                "query Hero { hero { name } }".to_owned(),
+                Some("Hero".to_owned())
+            )]
+        );
+    }
+}
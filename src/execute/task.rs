@@ -0,0 +1,114 @@
+use futures::{Future, Poll};
+use std::fmt;
+use std::sync::Arc;
+
+use super::cancel::CancellationToken;
+use super::panic::catch_unwind;
+use super::shared::SharedSchema;
+use request::{GraphQLRequest, GraphQLResponse};
+
+/// A task which executes a GraphQL request with the specified schema and context.
+///
+/// This is the unit of work spawned by the executors such as [`WithSpawner`].
+/// Since the task owns all values required for the execution, it can be sent to arbitrary
+/// task executors, stored in a queue, or executed inline by calling [`execute`].
+///
+/// As a `Future`, it completes in the first call of `poll`. It results in an error
+/// only if the task has been cancelled before being polled.
+///
+/// [`WithSpawner`]: ./type.WithSpawner.html
+/// [`execute`]: #method.execute
+pub struct GraphQLTask<S: SharedSchema> {
+    request: GraphQLRequest,
+    schema: Arc<S>,
+    context: S::Context,
+    token: CancellationToken,
+}
+
+impl<S: SharedSchema> fmt::Debug for GraphQLTask<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GraphQLTask")
+            .field("request", &self.request)
+            .field("token", &self.token)
+            .finish()
+    }
+}
+
+impl<S: SharedSchema> GraphQLTask<S> {
+    /// Creates a new `GraphQLTask` from the components.
+    pub fn new(request: GraphQLRequest, schema: Arc<S>, context: S::Context) -> GraphQLTask<S> {
+        GraphQLTask {
+            request,
+            schema,
+            context,
+            token: CancellationToken::new(),
+        }
+    }
+
+    /// Replaces the cancellation token associated with this task.
+    pub fn with_cancellation_token(self, token: CancellationToken) -> GraphQLTask<S> {
+        GraphQLTask { token, ..self }
+    }
+
+    /// Returns a reference to the GraphQL request executed by this task.
+    pub fn request(&self) -> &GraphQLRequest {
+        &self.request
+    }
+
+    /// Returns a reference to the GraphQL schema used in this task.
+    pub fn schema(&self) -> &Arc<S> {
+        &self.schema
+    }
+
+    /// Returns a reference to the context value passed to the resolvers.
+    pub fn context(&self) -> &S::Context {
+        &self.context
+    }
+
+    /// Returns a mutable reference to the context value passed to the resolvers.
+    pub fn context_mut(&mut self) -> &mut S::Context {
+        &mut self.context
+    }
+
+    /// Returns a reference to the cancellation token associated with this task.
+    pub fn cancellation_token(&self) -> &CancellationToken {
+        &self.token
+    }
+
+    /// Returns whether this task has been cancelled or not.
+    pub fn is_cancelled(&self) -> bool {
+        self.token.is_cancelled()
+    }
+
+    /// Consumes itself and returns the request, schema and context.
+    pub fn into_parts(self) -> (GraphQLRequest, Arc<S>, S::Context) {
+        (self.request, self.schema, self.context)
+    }
+
+    /// Executes the GraphQL request on the current thread.
+    ///
+    /// During the execution, the cancellation token of this task is visible to the resolvers
+    /// through [`cancel::current`], and a panic in the resolvers is converted into
+    /// a response with `500 Internal Server Error`.
+    ///
+    /// [`cancel::current`]: ./cancel/fn.current.html
+    pub fn execute(&self) -> GraphQLResponse {
+        let (request, schema, context) = (&self.request, &self.schema, &self.context);
+        self.token
+            .enter(|| catch_unwind(|| request.execute(schema.as_root_node(), context)))
+    }
+}
+
+impl<S: SharedSchema> Future for GraphQLTask<S> {
+    type Item = GraphQLResponse;
+    type Error = ();
+
+    #[inline]
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        if self.is_cancelled() {
+            trace!("the GraphQL task has been cancelled before execution");
+            return Err(());
+        }
+        Ok(self.execute().into())
+    }
+}
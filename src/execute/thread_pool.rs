@@ -0,0 +1,469 @@
+use finchers::error;
+use finchers::error::Error;
+
+use futures::sync::oneshot;
+use futures::task::{self, Task};
+use futures::{Async, Future, Poll};
+
+use http::StatusCode;
+use num_cpus;
+use std::fmt;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::cancel::{cancelled, CancelOnDrop};
+use super::custom::{custom, Custom, ExecutionStrategy};
+use super::shared::SharedSchema;
+use super::task::GraphQLTask;
+use request::{GraphQLRequest, GraphQLResponse};
+
+/// Create a GraphQL executor from the specified `RootNode` and the configuration of
+/// a dedicated worker pool.
+///
+/// The worker threads are started when this function is called, and the endpoint
+/// created by this wrapper will send the GraphQL queries to them after receiving the request.
+/// The pool can be inspected and shut down through the handle returned from
+/// [`ThreadPool::handle`].
+///
+/// [`ThreadPool::handle`]: ./type.ThreadPool.html#method.handle
+pub fn thread_pool<S>(schema: S, config: ThreadPoolConfig) -> ThreadPool<S>
+where
+    S: SharedSchema,
+{
+    custom(
+        schema,
+        ThreadPoolStrategy {
+            pool: config.build(),
+        },
+    )
+}
+
+/// The configuration of the worker pool used in [`thread_pool`].
+///
+/// [`thread_pool`]: ./fn.thread_pool.html
+#[derive(Debug, Clone)]
+pub struct ThreadPoolConfig {
+    pool_size: usize,
+    name_prefix: String,
+    stack_size: Option<usize>,
+}
+
+impl Default for ThreadPoolConfig {
+    fn default() -> ThreadPoolConfig {
+        ThreadPoolConfig {
+            pool_size: num_cpus::get(),
+            name_prefix: "graphql-worker-".into(),
+            stack_size: None,
+        }
+    }
+}
+
+impl ThreadPoolConfig {
+    /// Creates a new `ThreadPoolConfig` with the default values.
+    ///
+    /// By default, the number of worker threads is the same as the number of CPUs.
+    pub fn new() -> ThreadPoolConfig {
+        ThreadPoolConfig::default()
+    }
+
+    /// Sets the number of worker threads.
+    pub fn pool_size(self, pool_size: usize) -> ThreadPoolConfig {
+        assert!(pool_size > 0, "pool_size must be positive");
+        ThreadPoolConfig { pool_size, ..self }
+    }
+
+    /// Sets the prefix of the name of worker threads.
+    ///
+    /// The name of each worker is the concatenation of this prefix and its index.
+    pub fn name_prefix(self, name_prefix: impl Into<String>) -> ThreadPoolConfig {
+        ThreadPoolConfig {
+            name_prefix: name_prefix.into(),
+            ..self
+        }
+    }
+
+    /// Sets the stack size of worker threads, in bytes.
+    pub fn stack_size(self, stack_size: usize) -> ThreadPoolConfig {
+        ThreadPoolConfig {
+            stack_size: Some(stack_size),
+            ..self
+        }
+    }
+
+    fn build(&self) -> ThreadPoolHandle {
+        let (tx, rx) = mpsc::channel::<Job>();
+        let rx = Arc::new(Mutex::new(rx));
+        let lifecycle = Arc::new(Mutex::new(Lifecycle {
+            running: self.pool_size,
+            waiters: vec![],
+            next_id: 0,
+        }));
+
+        let workers: Vec<_> = (0..self.pool_size)
+            .map(|i| {
+                Arc::new(Mutex::new(WorkerStats {
+                    name: format!("{}{}", self.name_prefix, i),
+                    completed_tasks: 0,
+                    busy_time: Duration::from_secs(0),
+                    is_busy: false,
+                }))
+            })
+            .collect();
+
+        for stats in &workers {
+            let mut builder = thread::Builder::new().name(stats.lock().unwrap().name.clone());
+            if let Some(stack_size) = self.stack_size {
+                builder = builder.stack_size(stack_size);
+            }
+            let worker = Worker {
+                rx: rx.clone(),
+                stats: stats.clone(),
+                lifecycle: lifecycle.clone(),
+            };
+            builder
+                .spawn(move || worker.run())
+                .expect("failed to spawn a worker thread");
+        }
+
+        ThreadPoolHandle {
+            inner: Arc::new(Shared {
+                tx: Mutex::new(Some(tx)),
+                workers,
+                lifecycle,
+            }),
+        }
+    }
+}
+
+/// A GraphQL executor which sends the tasks to a dedicated worker pool.
+pub type ThreadPool<S> = Custom<S, ThreadPoolStrategy>;
+
+impl<S, A> Custom<S, ThreadPoolStrategy, A> {
+    /// Returns a handle to the worker pool owned by this executor.
+    pub fn handle(&self) -> ThreadPoolHandle {
+        self.strategy().handle()
+    }
+}
+
+/// The execution strategy used in [`ThreadPool`].
+///
+/// [`ThreadPool`]: ./type.ThreadPool.html
+#[derive(Debug)]
+pub struct ThreadPoolStrategy {
+    pool: ThreadPoolHandle,
+}
+
+impl ThreadPoolStrategy {
+    /// Returns a handle to the worker pool used in this strategy.
+    pub fn handle(&self) -> ThreadPoolHandle {
+        self.pool.clone()
+    }
+}
+
+impl<S> ExecutionStrategy<S, S::Context> for ThreadPoolStrategy
+where
+    S: SharedSchema,
+{
+    type Future = ThreadPoolFuture;
+
+    fn execute(
+        &self,
+        request: GraphQLRequest,
+        context: S::Context,
+        schema: Arc<S>,
+    ) -> Self::Future {
+        trace!("send a GraphQL task to the worker pool");
+        let task = GraphQLTask::new(request, schema, context);
+        let guard = CancelOnDrop(task.cancellation_token().clone());
+        let (tx, rx) = oneshot::channel();
+        let result = self.pool.execute(Box::new(move || {
+            if task.is_cancelled() {
+                trace!("the GraphQL task has been cancelled before execution");
+                return Box::new(|| ());
+            }
+            let response = task.execute();
+            Box::new(move || {
+                let _ = tx.send(response);
+            })
+        }));
+        ThreadPoolFuture {
+            state: match result {
+                Ok(()) => Ok(rx),
+                Err(err) => Err(Some(err)),
+            },
+            _guard: guard,
+        }
+    }
+}
+
+// The guard cancels the task when this future is dropped before completion.
+#[derive(Debug)]
+pub struct ThreadPoolFuture {
+    state: Result<oneshot::Receiver<GraphQLResponse>, Option<Error>>,
+    _guard: CancelOnDrop,
+}
+
+impl Future for ThreadPoolFuture {
+    type Item = GraphQLResponse;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match self.state {
+            Ok(ref mut rx) => rx.poll().map_err(|_| cancelled()),
+            Err(ref mut err) => Err(err.take().expect("the future has already polled")),
+        }
+    }
+}
+
+// ==== worker pool ====
+
+// A job returns the function which delivers its result, so that the worker can update
+// its statistics before the result is observed by the caller.
+type Job = Box<dyn FnOnce() -> Deliver + Send + 'static>;
+type Deliver = Box<dyn FnOnce()>;
+
+/// A handle to the worker pool owned by [`ThreadPool`].
+///
+/// The pool is shut down gracefully when [`shutdown`] is called or all handles
+/// (including the one held by the executor) are dropped.
+/// In both cases, the queued tasks are executed before the workers terminate.
+///
+/// [`ThreadPool`]: ./type.ThreadPool.html
+/// [`shutdown`]: #method.shutdown
+#[derive(Debug, Clone)]
+pub struct ThreadPoolHandle {
+    inner: Arc<Shared>,
+}
+
+#[derive(Debug)]
+struct Shared {
+    tx: Mutex<Option<mpsc::Sender<Job>>>,
+    workers: Vec<Arc<Mutex<WorkerStats>>>,
+    lifecycle: Arc<Mutex<Lifecycle>>,
+}
+
+#[derive(Debug)]
+struct Lifecycle {
+    running: usize,
+    waiters: Vec<(usize, Task)>,
+    next_id: usize,
+}
+
+impl ThreadPoolHandle {
+    fn execute(&self, job: Job) -> error::Result<()> {
+        let tx = self.inner.tx.lock().unwrap();
+        tx.as_ref().and_then(|tx| tx.send(job).ok()).ok_or_else(|| {
+            error::err_msg(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "the worker pool has been shut down",
+            )
+        })
+    }
+
+    /// Returns the snapshot of the statistics of each worker.
+    pub fn worker_stats(&self) -> Vec<WorkerStats> {
+        self.inner
+            .workers
+            .iter()
+            .map(|stats| stats.lock().unwrap().clone())
+            .collect()
+    }
+
+    /// Returns the number of running worker threads.
+    pub fn running_workers(&self) -> usize {
+        self.inner.lifecycle.lock().unwrap().running
+    }
+
+    /// Stops accepting new tasks and returns a future which will be resolved
+    /// when all workers have finished the queued tasks and terminated.
+    ///
+    /// After calling this method, the executor responds with `503 Service Unavailable`.
+    pub fn shutdown(&self) -> Shutdown {
+        self.inner.tx.lock().unwrap().take();
+        Shutdown {
+            lifecycle: self.inner.lifecycle.clone(),
+            id: None,
+        }
+    }
+}
+
+/// A future which will be resolved when all workers in the pool have terminated.
+#[derive(Debug)]
+pub struct Shutdown {
+    lifecycle: Arc<Mutex<Lifecycle>>,
+    id: Option<usize>,
+}
+
+impl Future for Shutdown {
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let mut lifecycle = self.lifecycle.lock().unwrap();
+        if lifecycle.running == 0 {
+            return Ok(Async::Ready(()));
+        }
+        match self.id {
+            Some(id) => {
+                if let Some(waiter) = lifecycle.waiters.iter_mut().find(|w| w.0 == id) {
+                    waiter.1 = task::current();
+                }
+            }
+            None => {
+                let id = lifecycle.next_id;
+                lifecycle.next_id = lifecycle.next_id.wrapping_add(1);
+                lifecycle.waiters.push((id, task::current()));
+                self.id = Some(id);
+            }
+        }
+        Ok(Async::NotReady)
+    }
+}
+
+impl Drop for Shutdown {
+    fn drop(&mut self) {
+        if let Some(id) = self.id.take() {
+            let mut lifecycle = self.lifecycle.lock().unwrap();
+            lifecycle.waiters.retain(|&(waiter, _)| waiter != id);
+        }
+    }
+}
+
+/// The statistics of a worker thread in the pool.
+#[derive(Debug, Clone)]
+pub struct WorkerStats {
+    name: String,
+    completed_tasks: u64,
+    busy_time: Duration,
+    is_busy: bool,
+}
+
+impl WorkerStats {
+    /// Returns the name of the worker thread.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the number of tasks completed by the worker.
+    pub fn completed_tasks(&self) -> u64 {
+        self.completed_tasks
+    }
+
+    /// Returns the total time spent by the worker for executing tasks.
+    pub fn busy_time(&self) -> Duration {
+        self.busy_time
+    }
+
+    /// Returns whether the worker is executing a task or not.
+    pub fn is_busy(&self) -> bool {
+        self.is_busy
+    }
+}
+
+struct Worker {
+    rx: Arc<Mutex<mpsc::Receiver<Job>>>,
+    stats: Arc<Mutex<WorkerStats>>,
+    lifecycle: Arc<Mutex<Lifecycle>>,
+}
+
+impl fmt::Debug for Worker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Worker")
+            .field("stats", &self.stats)
+            .finish()
+    }
+}
+
+impl Worker {
+    fn run(self) {
+        loop {
+            let job = match self.rx.lock().unwrap().recv() {
+                Ok(job) => job,
+                Err(..) => break,
+            };
+
+            self.stats.lock().unwrap().is_busy = true;
+            let start = Instant::now();
+            let deliver = job();
+            {
+                let mut stats = self.stats.lock().unwrap();
+                stats.is_busy = false;
+                stats.completed_tasks += 1;
+                stats.busy_time += start.elapsed();
+            }
+            deliver();
+        }
+        trace!("the worker thread is terminated");
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        let mut lifecycle = self.lifecycle.lock().unwrap();
+        lifecycle.running -= 1;
+        if lifecycle.running == 0 {
+            for (_, task) in lifecycle.waiters.drain(..) {
+                task.notify();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::sync::oneshot;
+    use futures::{future, Async, Future};
+    use std::sync::mpsc;
+
+    use super::{Deliver, ThreadPoolConfig};
+
+    #[test]
+    fn test_worker_stats() {
+        let pool = ThreadPoolConfig::new().pool_size(1).build();
+        let (tx, rx) = oneshot::channel();
+        pool.execute(Box::new(move || -> Deliver {
+            Box::new(move || {
+                let _ = tx.send(());
+            })
+        }))
+        .unwrap();
+        rx.wait().unwrap();
+
+        // The statistics are updated before the result is delivered.
+        let stats = pool.worker_stats();
+        assert_eq!(stats[0].name(), "graphql-worker-0");
+        assert_eq!(stats[0].completed_tasks(), 1);
+        assert!(!stats[0].is_busy());
+    }
+
+    #[test]
+    fn test_shutdown() {
+        let pool = ThreadPoolConfig::new().pool_size(1).build();
+        let (tx, rx) = mpsc::channel::<()>();
+        pool.execute(Box::new(move || -> Deliver {
+            rx.recv().unwrap();
+            Box::new(|| ())
+        }))
+        .unwrap();
+
+        let mut shutdown = pool.shutdown();
+        future::lazy(|| {
+            assert_matches!(shutdown.poll(), Ok(Async::NotReady));
+            assert_matches!(shutdown.poll(), Ok(Async::NotReady));
+            Ok::<(), ()>(())
+        })
+        .wait()
+        .unwrap();
+        assert_eq!(pool.inner.lifecycle.lock().unwrap().waiters.len(), 1);
+        assert!(pool
+            .execute(Box::new(|| -> Deliver { Box::new(|| ()) }))
+            .is_err());
+
+        tx.send(()).unwrap();
+        shutdown.wait().unwrap();
+        assert_eq!(pool.running_workers(), 0);
+        assert_eq!(pool.worker_stats()[0].completed_tasks(), 1);
+    }
+}
@@ -0,0 +1,244 @@
+//! The tracing extension in the Apollo tracing format.
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use juniper::meta::MetaType;
+use juniper::{Arguments, ExecutionResult, Executor, GraphQLType, Registry, Selection};
+use serde_json;
+use std::cell::RefCell;
+use std::time::{Duration, Instant};
+
+thread_local!(static CURRENT: RefCell<Option<Trace>> = RefCell::new(None));
+
+// The timings recorded during a GraphQL execution.
+#[derive(Debug)]
+struct Trace {
+    started: Instant,
+    resolvers: Vec<serde_json::Value>,
+}
+
+/// Runs the specified closure with recording the timings, and returns the result
+/// along with the value of `extensions.tracing`.
+pub(crate) fn trace<R>(f: impl FnOnce() -> R) -> (R, serde_json::Value) {
+    #[allow(missing_debug_implementations)]
+    struct ResetOnDrop(Option<Option<Trace>>);
+
+    impl Drop for ResetOnDrop {
+        fn drop(&mut self) {
+            if let Some(prev) = self.0.take() {
+                CURRENT.with(|current| current.replace(prev));
+            }
+        }
+    }
+
+    let start_time = Utc::now();
+    let started = Instant::now();
+    let prev = CURRENT.with(|current| {
+        current.replace(Some(Trace {
+            started,
+            resolvers: vec![],
+        }))
+    });
+    let mut reset = ResetOnDrop(Some(prev));
+
+    let result = f();
+
+    let duration = started.elapsed();
+    let end_time = Utc::now();
+    let prev = reset
+        .0
+        .take()
+        .expect("the previous trace has already restored");
+    let trace = CURRENT
+        .with(|current| current.replace(prev))
+        .expect("the trace has been removed during execution");
+
+    let tracing = json!({
+        "version": 1,
+        "startTime": rfc3339(start_time),
+        "endTime": rfc3339(end_time),
+        "duration": nanos(duration),
+        "execution": {
+            "resolvers": trace.resolvers,
+        },
+    });
+    (result, tracing)
+}
+
+fn rfc3339(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+fn nanos(duration: Duration) -> u64 {
+    duration.as_secs() * 1_000_000_000 + u64::from(duration.subsec_nanos())
+}
+
+/// A wrapper of the root types which records the durations of the resolvers of root fields.
+///
+/// Juniper does not provide the way to observe the execution of each resolver, and hence
+/// the tracing extension contains only the overall timings of execution by default.
+/// By wrapping the query or mutation type with this type, the resolvers of its fields
+/// are also reported in `execution.resolvers`, while the tracing is enabled.
+/// Note that their durations include the resolution of the nested fields, and the paths
+/// consist of the field names rather than the aliases.
+///
+/// # Example
+///
+/// ```
+/// # extern crate finchers_juniper;
+/// # extern crate juniper;
+/// # use finchers_juniper::execute::Traced;
+/// # use juniper::tests::model::Database;
+/// # use juniper::{EmptyMutation, RootNode};
+/// # fn main() {
+/// let schema = RootNode::new(
+///     Traced::new(Database::new()),
+///     EmptyMutation::<Database>::new(),
+/// );
+/// # drop(schema);
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Traced<T>(T);
+
+impl<T> Traced<T> {
+    /// Wraps the specified root type.
+    pub fn new(inner: T) -> Traced<T> {
+        Traced(inner)
+    }
+
+    /// Returns a reference to the underlying value.
+    pub fn get_ref(&self) -> &T {
+        &self.0
+    }
+
+    /// Consumes itself and returns the underlying value.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> GraphQLType for Traced<T>
+where
+    T: GraphQLType,
+{
+    type Context = T::Context;
+    type TypeInfo = T::TypeInfo;
+
+    fn name(info: &Self::TypeInfo) -> Option<&str> {
+        T::name(info)
+    }
+
+    fn meta<'r>(info: &Self::TypeInfo, registry: &mut Registry<'r>) -> MetaType<'r> {
+        T::meta(info, registry)
+    }
+
+    fn resolve_field(
+        &self,
+        info: &Self::TypeInfo,
+        field_name: &str,
+        arguments: &Arguments<'_>,
+        executor: &Executor<'_, Self::Context>,
+    ) -> ExecutionResult {
+        let is_tracing = CURRENT.with(|current| current.borrow().is_some());
+        if !is_tracing {
+            return self.0.resolve_field(info, field_name, arguments, executor);
+        }
+
+        let started = Instant::now();
+        let result = self.0.resolve_field(info, field_name, arguments, executor);
+        let duration = started.elapsed();
+
+        let parent_type = T::name(info).unwrap_or("");
+        let return_type = executor
+            .schema()
+            .concrete_type_by_name(parent_type)
+            .and_then(|parent| parent.field_by_name(field_name))
+            .map(|field| field.field_type.to_string());
+        CURRENT.with(|current| {
+            if let Some(ref mut trace) = *current.borrow_mut() {
+                let start_offset = started.duration_since(trace.started);
+                trace.resolvers.push(json!({
+                    "path": [field_name],
+                    "parentType": parent_type,
+                    "fieldName": field_name,
+                    "returnType": return_type,
+                    "startOffset": nanos(start_offset),
+                    "duration": nanos(duration),
+                }));
+            }
+        });
+
+        result
+    }
+
+    fn resolve_into_type(
+        &self,
+        info: &Self::TypeInfo,
+        type_name: &str,
+        selection_set: Option<&[Selection<'_>]>,
+        executor: &Executor<'_, Self::Context>,
+    ) -> ExecutionResult {
+        self.0
+            .resolve_into_type(info, type_name, selection_set, executor)
+    }
+
+    fn concrete_type_name(&self, context: &Self::Context, info: &Self::TypeInfo) -> String {
+        self.0.concrete_type_name(context, info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use finchers::prelude::*;
+    use finchers::test;
+    use http::Request;
+    use juniper::tests::model::Database;
+    use juniper::{EmptyMutation, RootNode};
+
+    use super::{trace, Traced};
+    use execute::current_thread;
+    use request::GraphQLRequest;
+    use testing::{database, database_schema, json_body};
+
+    #[test]
+    fn test_traced_resolvers() {
+        let schema = RootNode::new(
+            Traced::new(Database::new()),
+            EmptyMutation::<Database>::new(),
+        );
+        let request = GraphQLRequest::single(
+            "{ hero { name } droid(id: \"2001\") { id } }".into(),
+            None,
+            None,
+        );
+
+        let (response, tracing) = trace(|| request.execute(&schema, &Database::new()));
+        assert!(response.status().is_success());
+        assert_eq!(tracing["version"], 1);
+        let resolvers = tracing["execution"]["resolvers"].as_array().unwrap();
+        assert_eq!(resolvers.len(), 2);
+        assert_eq!(resolvers[0]["path"], json!(["hero"]));
+        assert_eq!(resolvers[0]["parentType"], "Query");
+        assert_eq!(resolvers[0]["returnType"], "Character");
+        assert_eq!(resolvers[1]["fieldName"], "droid");
+
+        // the resolvers are not recorded outside of tracing.
+        let response = request.execute(&schema, &Database::new());
+        assert!(response.status().is_success());
+    }
+
+    #[test]
+    fn test_tracing_header() {
+        let is_traced = |tracing: bool| {
+            let endpoint = database().wrap(current_thread(database_schema()).tracing(tracing));
+            let response = test::runner(endpoint)
+                .perform(Request::get("/?query={hero{name}}").header("x-apollo-tracing", "1"))
+                .unwrap();
+            json_body(&response)["extensions"]["tracing"]["version"] == 1
+        };
+
+        // The header is ignored unless the executor enables the tracing extension.
+        assert!(!is_traced(false));
+        assert!(is_traced(true));
+    }
+}
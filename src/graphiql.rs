@@ -1,4 +1,11 @@
 //! Endpoint for serving GraphiQL source.
+//!
+//! By default, the generated interface loads its scripts and stylesheets from CDN.
+//! If the feature `bundled-graphiql` is enabled, they are embedded in this crate and
+//! served from the sub-paths of the endpoint, e.g. `/graphiql/graphiql.min.js`, so that
+//! the interface works without the access to the Internet.  In that case, the endpoint
+//! should be mounted on a path which does not end with `/`, such as `path!(/ "graphiql")`,
+//! in order to receive the requests to the sub-paths.
 
 use finchers::endpoint::{ApplyContext, ApplyResult, Endpoint};
 use finchers::error::Error;
@@ -6,13 +13,16 @@ use finchers::error::Error;
 use futures::{Future, Poll};
 
 use bytes::Bytes;
+#[cfg(feature = "bundled-graphiql")]
+use finchers::endpoint::ApplyError;
 use http::{header, Response};
+#[cfg(not(feature = "bundled-graphiql"))]
 use juniper;
 
 /// Creates an endpoint which returns a generated GraphiQL interface.
 pub fn graphiql_source(endpoint_url: impl AsRef<str>) -> GraphiQLSource {
     GraphiQLSource {
-        source: juniper::http::graphiql::graphiql_source(endpoint_url.as_ref()).into(),
+        source: generate(endpoint_url.as_ref()).into(),
     }
 }
 
@@ -25,32 +35,244 @@ pub struct GraphiQLSource {
 impl GraphiQLSource {
     /// Regenerate the GraphiQL interface with the specified endpoint URL.
     pub fn regenerate(&mut self, endpoint_url: impl AsRef<str>) {
-        self.source = juniper::http::graphiql::graphiql_source(endpoint_url.as_ref()).into();
+        self.source = generate(endpoint_url.as_ref()).into();
     }
 }
 
+#[cfg(not(feature = "bundled-graphiql"))]
+fn generate(endpoint_url: &str) -> String {
+    juniper::http::graphiql::graphiql_source(endpoint_url)
+}
+
+#[cfg(not(feature = "bundled-graphiql"))]
 impl<'a> Endpoint<'a> for GraphiQLSource {
     type Output = (Response<Bytes>,);
-    type Future = GraphiQLFuture<'a>;
+    type Future = GraphiQLFuture;
 
     fn apply(&'a self, _: &mut ApplyContext<'_>) -> ApplyResult<Self::Future> {
-        Ok(GraphiQLFuture(&self.source))
+        Ok(GraphiQLFuture {
+            body: self.source.clone(),
+            content_type: "text/html; charset=utf-8",
+            cache_control: None,
+        })
     }
 }
 
 #[doc(hidden)]
 #[derive(Debug)]
-pub struct GraphiQLFuture<'a>(&'a Bytes);
+pub struct GraphiQLFuture {
+    body: Bytes,
+    content_type: &'static str,
+    cache_control: Option<&'static str>,
+}
 
-impl<'a> Future for GraphiQLFuture<'a> {
+impl Future for GraphiQLFuture {
     type Item = (Response<Bytes>,);
     type Error = Error;
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        Ok((Response::builder()
-            .header(header::CONTENT_TYPE, "text/html; charset=utf-8")
-            .body(self.0.clone())
+        let mut response = Response::builder();
+        response.header(header::CONTENT_TYPE, self.content_type);
+        if let Some(cache_control) = self.cache_control {
+            response.header(header::CACHE_CONTROL, cache_control);
+        }
+        Ok((response
+            .body(self.body.clone())
             .expect("should be a valid response"),)
             .into())
     }
 }
+
+// ==== bundled assets ====
+
+#[cfg(feature = "bundled-graphiql")]
+mod bundled {
+    /// The placeholder in the generated source, which is replaced with the relative path
+    /// to the directory of assets for each request.
+    pub(super) const BASE: &str = "{{GRAPHIQL_ASSETS}}";
+
+    /// The name, content type and content of the embedded assets.
+    ///
+    /// The files are vendored in `assets/graphiql` (see `assets/graphiql/fetch.sh`).
+    pub(super) const ASSETS: &[(&str, &str, &[u8])] = &[
+        (
+            "graphiql.css",
+            "text/css; charset=utf-8",
+            include_bytes!("../assets/graphiql/graphiql.css"),
+        ),
+        (
+            "graphiql.min.js",
+            "application/javascript; charset=utf-8",
+            include_bytes!("../assets/graphiql/graphiql.min.js"),
+        ),
+        (
+            "fetch.js",
+            "application/javascript; charset=utf-8",
+            include_bytes!("../assets/graphiql/fetch.js"),
+        ),
+        (
+            "react.production.min.js",
+            "application/javascript; charset=utf-8",
+            include_bytes!("../assets/graphiql/react.production.min.js"),
+        ),
+        (
+            "react-dom.production.min.js",
+            "application/javascript; charset=utf-8",
+            include_bytes!("../assets/graphiql/react-dom.production.min.js"),
+        ),
+    ];
+
+    // The same interface as `juniper::http::graphiql::graphiql_source`,
+    // except for the locations of the assets.
+    pub(super) fn generate(endpoint_url: &str) -> String {
+        // Quoted as a JSON string, which is also a valid JavaScript literal.
+        // The `</` is escaped so as not to close the script element.
+        let graphql_url = json!(endpoint_url).to_string().replace("</", "<\\/");
+        format!(
+            r#"
+<!DOCTYPE html>
+<html>
+<head>
+    <title>GraphQL</title>
+    <style>
+        html, body, #app {{
+            height: 100%;
+            margin: 0;
+            overflow: hidden;
+            width: 100%;
+        }}
+    </style>
+    <link rel="stylesheet" type="text/css" href="{base}/graphiql.css">
+</head>
+<body>
+    <div id="app"></div>
+    <script src="{base}/fetch.js"></script>
+    <script src="{base}/react.production.min.js"></script>
+    <script src="{base}/react-dom.production.min.js"></script>
+    <script src="{base}/graphiql.min.js"></script>
+    <script>var GRAPHQL_URL = {graphql_url};</script>
+    <script>
+        function graphQLFetcher(params) {{
+            return fetch(GRAPHQL_URL, {{
+                method: 'post',
+                headers: {{
+                    'Accept': 'application/json',
+                    'Content-Type': 'application/json',
+                }},
+                credentials: 'include',
+                body: JSON.stringify(params)
+            }}).then(function (response) {{
+                return response.text();
+            }}).then(function (body) {{
+                try {{
+                    return JSON.parse(body);
+                }} catch (error) {{
+                    return body;
+                }}
+            }});
+        }}
+        ReactDOM.render(
+            React.createElement(GraphiQL, {{
+                fetcher: graphQLFetcher,
+            }}),
+            document.querySelector('#app'));
+    </script>
+</body>
+</html>
+"#,
+            base = BASE,
+            graphql_url = graphql_url,
+        )
+    }
+}
+
+#[cfg(feature = "bundled-graphiql")]
+use self::bundled::generate;
+
+#[cfg(feature = "bundled-graphiql")]
+impl<'a> Endpoint<'a> for GraphiQLSource {
+    type Output = (Response<Bytes>,);
+    type Future = GraphiQLFuture;
+
+    fn apply(&'a self, cx: &mut ApplyContext<'_>) -> ApplyResult<Self::Future> {
+        let asset = match cx.next_segment() {
+            Some(name) => Some(
+                bundled::ASSETS
+                    .iter()
+                    .find(|&&(asset, _, _)| name == asset)
+                    .ok_or_else(ApplyError::not_matched)?,
+            ),
+            None => None,
+        };
+        if cx.next_segment().is_some() {
+            return Err(ApplyError::not_matched());
+        }
+
+        match asset {
+            Some(&(_, content_type, content)) => Ok(GraphiQLFuture {
+                body: Bytes::from_static(content),
+                content_type,
+                cache_control: Some("public, max-age=86400"),
+            }),
+            None => {
+                // The assets are referred by the relative path, so that the interface
+                // also works behind a reverse proxy which rewrites the path.
+                let path = cx.input().uri().path();
+                let base = if path.ends_with('/') {
+                    "."
+                } else {
+                    path.rsplit('/').next().unwrap_or(".")
+                };
+                let source = String::from_utf8_lossy(&self.source).replace(bundled::BASE, base);
+                Ok(GraphiQLFuture {
+                    body: source.into(),
+                    content_type: "text/html; charset=utf-8",
+                    cache_control: None,
+                })
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "bundled-graphiql"))]
+mod tests {
+    use finchers::endpoint::syntax;
+    use finchers::prelude::*;
+    use finchers::test;
+    use http::StatusCode;
+
+    use super::graphiql_source;
+
+    #[test]
+    fn test_bundled_assets() {
+        let mut runner = test::runner(syntax::segment("graphiql").and(graphiql_source("/graphql")));
+
+        let response = runner.perform("/graphiql").unwrap();
+        let body = response.body().to_utf8().unwrap();
+        assert!(body.contains(r#"<script src="graphiql/graphiql.min.js"></script>"#));
+
+        let response = runner.perform("/graphiql/").unwrap();
+        let body = response.body().to_utf8().unwrap();
+        assert!(body.contains(r#"<script src="./graphiql.min.js"></script>"#));
+
+        let response = runner.perform("/graphiql/graphiql.min.js").unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()["content-type"],
+            "application/javascript; charset=utf-8"
+        );
+
+        let response = runner.perform("/graphiql/unknown.js").unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn test_escape_graphql_url() {
+        let mut runner = test::runner(
+            syntax::segment("graphiql").and(graphiql_source(r#"/graphql?a='b'\</script>"#)),
+        );
+        let response = runner.perform("/graphiql").unwrap();
+        let body = response.body().to_utf8().unwrap();
+        assert!(body.contains(r#"var GRAPHQL_URL = "/graphql?a='b'\\<\/script>";"#));
+    }
+}
@@ -62,28 +62,52 @@
 #![cfg_attr(test, deny(warnings))]
 #![cfg_attr(test, doc(test(attr(deny(warnings)))))]
 
+#[cfg(feature = "brotli")]
+extern crate brotli;
+extern crate backtrace;
 extern crate bytes;
+extern crate chrono;
 extern crate failure;
 extern crate finchers;
+extern crate flate2;
 #[macro_use]
 extern crate futures;
+#[cfg_attr(test, macro_use)]
 extern crate juniper;
 #[macro_use]
 extern crate log;
+extern crate num_cpus;
 extern crate percent_encoding;
 #[macro_use]
 extern crate serde;
 extern crate http;
+#[macro_use]
 extern crate serde_json;
 extern crate serde_qs;
 
+#[cfg(test)]
+extern crate futures_cpupool;
 #[cfg(test)]
 #[macro_use]
 extern crate matches;
+#[cfg(test)]
+extern crate tokio;
 
+mod compression;
+pub mod context;
+pub mod cors;
+mod csrf;
+mod document;
 pub mod execute;
 pub mod graphiql;
+pub mod metrics;
+pub mod playground;
 pub mod request;
+pub mod request_id;
+#[cfg(test)]
+mod testing;
+pub mod usage;
 
 pub use graphiql::graphiql_source;
+pub use playground::playground_source;
 pub use request::graphql_request;
@@ -0,0 +1,480 @@
+//! Metrics of GraphQL traffic in the Prometheus text exposition format.
+//!
+//! The executors update the metrics registered by [`Custom::metrics`], and the endpoint
+//! created by [`metrics_endpoint`] renders them as follows:
+//!
+//! * `graphql_operations_total{operation_name, operation_type}` (counter)
+//! * `graphql_errors_total{code}` (counter)
+//! * `graphql_operation_duration_seconds{operation_type}` (histogram)
+//! * `graphql_requests_in_flight` (gauge)
+//! * `graphql_batch_size` (histogram)
+//!
+//! The operations served from the response cache or shared with an identical request
+//! are also counted in `graphql_operations_total`, but not in the durations.
+//!
+//! The error code is taken from `extensions.code` of each error. The errors without the code
+//! are counted as `RESOLVER_ERROR` if the operation has been executed, or `INVALID_REQUEST`
+//! if the request has been rejected before execution. The HTTP requests rejected without
+//! a GraphQL response (e.g. `405 Method Not Allowed` or `503 Service Unavailable`) are
+//! counted with the code derived from the status, such as `METHOD_NOT_ALLOWED`.
+//!
+//! [`Custom::metrics`]: ../execute/struct.Custom.html#method.metrics
+//! [`metrics_endpoint`]: ./fn.metrics_endpoint.html
+
+use finchers::endpoint::{ApplyContext, ApplyResult, Endpoint};
+use finchers::error::Error;
+
+use futures::{Future, Poll};
+
+use http::{header, Response, StatusCode};
+use serde_json;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use document::OperationKind;
+use request::SingleRequest;
+
+/// The maximum number of distinct operation names to be recorded.
+///
+/// Since the operation names are chosen by the clients, the operations exceeding
+/// this limit are aggregated into the operation name `__other__`.
+const MAX_OPERATIONS: usize = 1000;
+
+const DURATION_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+const BATCH_SIZE_BUCKETS: &[f64] = &[1.0, 2.0, 5.0, 10.0, 20.0, 50.0, 100.0];
+
+/// A registry of the metrics of GraphQL traffic.
+#[derive(Debug)]
+pub struct Metrics {
+    state: Mutex<State>,
+    in_flight: AtomicUsize,
+}
+
+#[derive(Debug)]
+struct State {
+    operations: HashMap<(String, &'static str), u64>,
+    errors: HashMap<String, u64>,
+    durations: HashMap<&'static str, Histogram>,
+    batch_sizes: Histogram,
+}
+
+impl Default for Metrics {
+    fn default() -> Metrics {
+        Metrics {
+            state: Mutex::new(State {
+                operations: HashMap::new(),
+                errors: HashMap::new(),
+                durations: HashMap::new(),
+                batch_sizes: Histogram::new(BATCH_SIZE_BUCKETS),
+            }),
+            in_flight: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl Metrics {
+    /// Creates an empty `Metrics`.
+    pub fn new() -> Metrics {
+        Metrics::default()
+    }
+
+    /// Returns the number of GraphQL requests being executed.
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+
+    // The duration is `None` if the response has been served without execution.
+    pub(crate) fn observe_operation(
+        &self,
+        request: &SingleRequest,
+        duration: Option<Duration>,
+        response: &serde_json::Value,
+        is_ok: bool,
+    ) {
+        let document = request.document();
+        let operation = document.and_then(|document| document.operation(request.operation_name()));
+        let operation_name = request
+            .operation_name()
+            .or_else(|| operation.and_then(|operation| operation.name()))
+            .unwrap_or("");
+        let operation_type = operation.map_or("unknown", |operation| match operation.kind() {
+            OperationKind::Query => "query",
+            OperationKind::Mutation => "mutation",
+            OperationKind::Subscription => "subscription",
+        });
+        let default_code = if is_ok {
+            "RESOLVER_ERROR"
+        } else {
+            "INVALID_REQUEST"
+        };
+
+        let mut state = self.state.lock().unwrap();
+
+        let mut key = (operation_name.to_owned(), operation_type);
+        if !state.operations.contains_key(&key) && state.operations.len() >= MAX_OPERATIONS {
+            key.0 = "__other__".to_owned();
+        }
+        *state.operations.entry(key).or_insert(0) += 1;
+
+        if let Some(duration) = duration {
+            state
+                .durations
+                .entry(operation_type)
+                .or_insert_with(|| Histogram::new(DURATION_BUCKETS))
+                .observe(duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) * 1e-9);
+        }
+
+        let errors = response.get("errors").and_then(|errors| errors.as_array());
+        for error in errors.into_iter().flatten() {
+            let code = error
+                .get("extensions")
+                .and_then(|extensions| extensions.get("code"))
+                .and_then(|code| code.as_str())
+                .unwrap_or(default_code);
+            state.record_error(code);
+        }
+    }
+
+    pub(crate) fn observe_error(&self, code: &str) {
+        self.state.lock().unwrap().record_error(code);
+    }
+
+    pub(crate) fn observe_rejection(&self, status: StatusCode) {
+        let code = status
+            .canonical_reason()
+            .unwrap_or("UNKNOWN")
+            .to_ascii_uppercase()
+            .replace(|c: char| !c.is_ascii_alphanumeric(), "_");
+        self.observe_error(&code);
+    }
+
+    pub(crate) fn observe_batch_size(&self, size: usize) {
+        self.state.lock().unwrap().batch_sizes.observe(size as f64);
+    }
+
+    pub(crate) fn start(metrics: &Arc<Metrics>) -> InFlight {
+        metrics.in_flight.fetch_add(1, Ordering::SeqCst);
+        InFlight(metrics.clone())
+    }
+
+    /// Renders the metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let state = self.state.lock().unwrap();
+        let mut out = String::new();
+
+        header(
+            &mut out,
+            "graphql_operations_total",
+            "counter",
+            "The number of executed GraphQL operations.",
+        );
+        let operations: BTreeMap<_, _> = state.operations.iter().collect();
+        for (&(ref name, operation_type), count) in operations {
+            let _ = writeln!(
+                out,
+                "graphql_operations_total{{operation_name=\"{}\",operation_type=\"{}\"}} {}",
+                escape(name),
+                operation_type,
+                count
+            );
+        }
+
+        header(
+            &mut out,
+            "graphql_errors_total",
+            "counter",
+            "The number of GraphQL errors by code.",
+        );
+        let errors: BTreeMap<_, _> = state.errors.iter().collect();
+        for (code, count) in errors {
+            let _ = writeln!(
+                out,
+                "graphql_errors_total{{code=\"{}\"}} {}",
+                escape(code),
+                count
+            );
+        }
+
+        header(
+            &mut out,
+            "graphql_operation_duration_seconds",
+            "histogram",
+            "The duration of GraphQL operations in seconds.",
+        );
+        let durations: BTreeMap<_, _> = state.durations.iter().collect();
+        for (operation_type, histogram) in durations {
+            histogram.render(
+                &mut out,
+                "graphql_operation_duration_seconds",
+                &format!("operation_type=\"{}\"", operation_type),
+            );
+        }
+
+        header(
+            &mut out,
+            "graphql_requests_in_flight",
+            "gauge",
+            "The number of GraphQL requests being executed.",
+        );
+        let _ = writeln!(out, "graphql_requests_in_flight {}", self.in_flight());
+
+        header(
+            &mut out,
+            "graphql_batch_size",
+            "histogram",
+            "The number of operations in a GraphQL request.",
+        );
+        state.batch_sizes.render(&mut out, "graphql_batch_size", "");
+
+        out
+    }
+}
+
+impl State {
+    fn record_error(&mut self, code: &str) {
+        if !self.errors.contains_key(code) && self.errors.len() >= MAX_OPERATIONS {
+            return;
+        }
+        *self.errors.entry(code.to_owned()).or_insert(0) += 1;
+    }
+}
+
+/// A guard which marks a GraphQL request as being executed until dropped.
+#[derive(Debug)]
+pub(crate) struct InFlight(Arc<Metrics>);
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[derive(Debug)]
+struct Histogram {
+    bounds: &'static [f64],
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Histogram {
+        Histogram {
+            bounds,
+            counts: vec![0; bounds.len()],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    fn observe(&mut self, value: f64) {
+        if let Some(i) = self.bounds.iter().position(|&bound| value <= bound) {
+            self.counts[i] += 1;
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        let sep = if labels.is_empty() { "" } else { "," };
+        let mut cumulative = 0;
+        for (bound, count) in self.bounds.iter().zip(&self.counts) {
+            cumulative += count;
+            let _ = writeln!(
+                out,
+                "{}_bucket{{{}{}le=\"{}\"}} {}",
+                name, labels, sep, bound, cumulative
+            );
+        }
+        let _ = writeln!(
+            out,
+            "{}_bucket{{{}{}le=\"+Inf\"}} {}",
+            name, labels, sep, self.count
+        );
+        let labels = if labels.is_empty() {
+            String::new()
+        } else {
+            format!("{{{}}}", labels)
+        };
+        let _ = writeln!(out, "{}_sum{} {}", name, labels, self.sum);
+        let _ = writeln!(out, "{}_count{} {}", name, labels, self.count);
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+// Escapes a label value, as described in the Prometheus text exposition format.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Creates an endpoint which renders the specified metrics in the Prometheus text
+/// exposition format.
+pub fn metrics_endpoint(metrics: Arc<Metrics>) -> MetricsEndpoint {
+    MetricsEndpoint { metrics }
+}
+
+#[allow(missing_docs)]
+#[derive(Debug)]
+pub struct MetricsEndpoint {
+    metrics: Arc<Metrics>,
+}
+
+impl<'a> Endpoint<'a> for MetricsEndpoint {
+    type Output = (Response<String>,);
+    type Future = MetricsFuture<'a>;
+
+    fn apply(&'a self, _: &mut ApplyContext<'_>) -> ApplyResult<Self::Future> {
+        Ok(MetricsFuture(&self.metrics))
+    }
+}
+
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct MetricsFuture<'a>(&'a Metrics);
+
+impl<'a> Future for MetricsFuture<'a> {
+    type Item = (Response<String>,);
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        Ok((Response::builder()
+            .header(header::CONTENT_TYPE, "text/plain; version=0.0.4")
+            .body(self.0.render())
+            .expect("should be a valid response"),)
+            .into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use finchers::prelude::*;
+    use finchers::test;
+    use http::Request;
+    use juniper::tests::model::Database;
+    use std::sync::Arc;
+
+    use std::time::Duration;
+
+    use super::{metrics_endpoint, Metrics};
+    use context::bearer_token;
+    use execute::{current_thread, InMemoryCache};
+    use request::CsrfPrevention;
+    use testing::{database, database_schema};
+
+    fn render(metrics: &Arc<Metrics>) -> String {
+        let mut runner = test::runner(metrics_endpoint(metrics.clone()));
+        let response = runner.perform("/").unwrap();
+        response.body().to_utf8().unwrap().into_owned()
+    }
+
+    #[test]
+    fn test_metrics() {
+        let schema = database_schema();
+        let metrics = Arc::new(Metrics::new());
+        let endpoint = database().wrap(current_thread(schema).metrics(metrics.clone()));
+        let mut runner = test::runner(endpoint);
+
+        runner
+            .perform(Request::get("/?query=query%20Hero{hero{name}}"))
+            .unwrap();
+        runner
+            .perform(Request::get("/?query={hero{unknown}}"))
+            .unwrap();
+        assert_eq!(metrics.in_flight(), 0);
+
+        let mut runner = test::runner(metrics_endpoint(metrics.clone()));
+        let response = runner.perform("/").unwrap();
+        assert_eq!(
+            response.headers().get("content-type").unwrap(),
+            "text/plain; version=0.0.4"
+        );
+        let body = response.body().to_utf8().unwrap().into_owned();
+        assert!(body.contains(
+            "graphql_operations_total{operation_name=\"Hero\",operation_type=\"query\"} 1\n"
+        ));
+        assert!(body.contains(
+            "graphql_operations_total{operation_name=\"\",operation_type=\"query\"} 1\n"
+        ));
+        assert!(body.contains("graphql_errors_total{code=\"INVALID_REQUEST\"} 1\n"));
+        assert!(
+            body.contains("graphql_operation_duration_seconds_count{operation_type=\"query\"} 2\n")
+        );
+        assert!(body.contains("graphql_requests_in_flight 0\n"));
+        assert!(body.contains("graphql_batch_size_bucket{le=\"1\"} 2\n"));
+        assert!(body.contains("graphql_batch_size_count 2\n"));
+    }
+
+    #[test]
+    fn test_metrics_cache_hit() {
+        let schema = database_schema();
+        let metrics = Arc::new(Metrics::new());
+        let cache = InMemoryCache::new(16, Duration::from_secs(3600));
+        let endpoint = database().wrap(
+            current_thread(schema)
+                .response_cache(Arc::new(cache), |_: &Database| Some("".into()))
+                .metrics(metrics.clone()),
+        );
+        let mut runner = test::runner(endpoint);
+        for _ in 0..2 {
+            runner
+                .perform(Request::get("/?query=query%20Hero{hero{name}}"))
+                .unwrap();
+        }
+
+        let body = render(&metrics);
+        assert!(body.contains(
+            "graphql_operations_total{operation_name=\"Hero\",operation_type=\"query\"} 2\n"
+        ));
+        assert!(
+            body.contains("graphql_operation_duration_seconds_count{operation_type=\"query\"} 1\n")
+        );
+    }
+
+    #[test]
+    fn test_metrics_rejections() {
+        let schema = database_schema();
+        let metrics = Arc::new(Metrics::new());
+        let endpoint = bearer_token()
+            .required()
+            .map(|_: String| Database::new())
+            .wrap(
+                current_thread(schema)
+                    .csrf_prevention(CsrfPrevention::new())
+                    .metrics(metrics.clone()),
+            );
+        let mut runner = test::runner(endpoint);
+
+        // missing credentials
+        runner
+            .perform(Request::get("/?query={hero{name}}").header("x-graphql-csrf", "1"))
+            .unwrap();
+        // potential CSRF
+        let _ = runner
+            .perform(Request::get("/?query={hero{name}}").header("authorization", "Bearer x"));
+        // unsupported method
+        let _ = runner.perform(
+            Request::put("/?query={hero{name}}")
+                .header("authorization", "Bearer x")
+                .header("x-graphql-csrf", "1"),
+        );
+
+        let body = render(&metrics);
+        assert!(body.contains("graphql_errors_total{code=\"UNAUTHENTICATED\"} 1\n"));
+        assert!(body.contains("graphql_errors_total{code=\"BAD_REQUEST\"} 1\n"));
+        assert!(body.contains("graphql_errors_total{code=\"METHOD_NOT_ALLOWED\"} 1\n"));
+        assert!(!body.contains("graphql_operations_total{"));
+    }
+}
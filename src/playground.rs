@@ -0,0 +1,127 @@
+//! Endpoint for serving GraphQL Playground source.
+
+use finchers::endpoint::{ApplyContext, ApplyResult, Endpoint};
+use finchers::error::Error;
+
+use futures::{Future, Poll};
+
+use bytes::Bytes;
+use http::{header, Response};
+
+/// Creates an endpoint which returns a generated GraphQL Playground interface.
+///
+/// The `subscriptionEndpoint` setting is sent only if `subscriptions_url` is specified.
+pub fn playground_source(
+    endpoint_url: impl AsRef<str>,
+    subscriptions_url: Option<&str>,
+) -> PlaygroundSource {
+    PlaygroundSource {
+        source: generate(endpoint_url.as_ref(), subscriptions_url).into(),
+    }
+}
+
+#[allow(missing_docs)]
+#[derive(Debug)]
+pub struct PlaygroundSource {
+    source: Bytes,
+}
+
+impl PlaygroundSource {
+    /// Regenerate the GraphQL Playground interface with the specified URLs.
+    pub fn regenerate(&mut self, endpoint_url: impl AsRef<str>, subscriptions_url: Option<&str>) {
+        self.source = generate(endpoint_url.as_ref(), subscriptions_url).into();
+    }
+}
+
+/// The version of `graphql-playground-react` loaded from CDN.
+const PLAYGROUND_VERSION: &str = "1.7.20";
+
+fn generate(endpoint_url: &str, subscriptions_url: Option<&str>) -> String {
+    let mut settings = json!({ "endpoint": endpoint_url });
+    if let Some(subscriptions_url) = subscriptions_url {
+        settings["subscriptionEndpoint"] = json!(subscriptions_url);
+    }
+    // Prevents the URLs from closing the script element.
+    let settings = settings.to_string().replace("</", "<\\/");
+
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+    <meta charset="utf-8">
+    <meta name="viewport" content="user-scalable=no, initial-scale=1.0, minimum-scale=1.0, maximum-scale=1.0, minimal-ui">
+    <title>GraphQL Playground</title>
+    <link rel="stylesheet" href="//cdn.jsdelivr.net/npm/graphql-playground-react@{version}/build/static/css/index.css">
+    <link rel="shortcut icon" href="//cdn.jsdelivr.net/npm/graphql-playground-react@{version}/build/favicon.png">
+    <script src="//cdn.jsdelivr.net/npm/graphql-playground-react@{version}/build/static/js/middleware.js"></script>
+</head>
+<body>
+    <div id="root"></div>
+    <script>
+        window.addEventListener('load', function (event) {{
+            GraphQLPlayground.init(document.getElementById('root'), {settings});
+        }});
+    </script>
+</body>
+</html>
+"#,
+        settings = settings,
+        version = PLAYGROUND_VERSION,
+    )
+}
+
+impl<'a> Endpoint<'a> for PlaygroundSource {
+    type Output = (Response<Bytes>,);
+    type Future = PlaygroundFuture<'a>;
+
+    fn apply(&'a self, _: &mut ApplyContext<'_>) -> ApplyResult<Self::Future> {
+        Ok(PlaygroundFuture(&self.source))
+    }
+}
+
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct PlaygroundFuture<'a>(&'a Bytes);
+
+impl<'a> Future for PlaygroundFuture<'a> {
+    type Item = (Response<Bytes>,);
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        Ok((Response::builder()
+            .header(header::CONTENT_TYPE, "text/html; charset=utf-8")
+            .body(self.0.clone())
+            .expect("should be a valid response"),)
+            .into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use finchers::test;
+
+    use super::playground_source;
+
+    #[test]
+    fn test_playground_source() {
+        let mut runner = test::runner(playground_source(
+            "/graphql",
+            Some("ws://localhost:4000/</script>"),
+        ));
+        let response = runner.perform("/").unwrap();
+        assert_eq!(
+            response.headers()["content-type"],
+            "text/html; charset=utf-8"
+        );
+        let body = response.body().to_utf8().unwrap();
+        assert!(body.contains(
+            r#"{"endpoint":"/graphql","subscriptionEndpoint":"ws://localhost:4000/<\/script>"}"#
+        ));
+        assert!(body.contains("graphql-playground-react@1.7.20/"));
+
+        let mut runner = test::runner(playground_source("/graphql", None));
+        let response = runner.perform("/").unwrap();
+        let body = response.body().to_utf8().unwrap();
+        assert!(body.contains(r#"{"endpoint":"/graphql"}"#));
+    }
+}
@@ -1,45 +1,119 @@
 //! Endpoint for parsing GraphQL request.
 
 use finchers::endpoint::with_get_cx;
-use finchers::endpoint::{ApplyContext, ApplyResult, Endpoint};
+use finchers::endpoint::{ApplyContext, ApplyError, ApplyResult, Endpoint};
 use finchers::endpoints::body;
 use finchers::error;
-use finchers::error::Error;
+use finchers::error::{Error, HttpError};
+use finchers::input::Input;
 use finchers::output::{Output, OutputContext};
 
 use futures::{Future, Poll};
 
 use juniper;
-use juniper::{GraphQLType, InputValue, RootNode};
+use juniper::{GraphQLType, InputValue, RootNode, Variables};
 
 use failure::SyncFailure;
+use http::header::HeaderValue;
 use http::Method;
-use http::{header, Response, StatusCode};
+use http::{header, HeaderMap, Response, StatusCode};
 use percent_encoding::percent_decode;
+use serde::{Deserialize, Deserializer};
 use serde_json;
 use serde_qs;
+use std::fmt;
+use std::slice;
+use std::sync::Arc;
+use std::thread;
+use std::time::Instant;
+
+pub use compression::Compression;
+pub use csrf::CsrfPrevention;
+use document::{Document, OperationKind};
+use execute::limit::Permit;
+use execute::tracing::trace;
+use execute::{AccessLog, CacheHint, CacheScope, SlowOperationLog};
+use metrics::Metrics;
+use request_id::RequestId;
+use usage::FieldUsage;
 
 /// Create an endpoint which parses a GraphQL request from the client.
 ///
-/// This endpoint validates if the HTTP method is GET or POST and if the iterator over remaining
-/// segments is empty, and skips if the request is not acceptable.
+/// This endpoint validates if the HTTP method is GET, HEAD or POST, and rejects the request
+/// with `405 Method Not Allowed` otherwise (see also [`skip_unsupported_methods`]).
 /// If the validation is successed, it will return a Future which behaves as follows:
 ///
-/// * If the method is `GET`, the query in the request is parsed as a single GraphQL query.
-///   If the query string is missing, it will return an error.
+/// * If the method is `GET` or `HEAD`, the query in the request is parsed as a single
+///   GraphQL query.  If the query string is missing, it will return an error.
 /// * If the method is `POST`, receives the all contents of the request body and then converts
 ///   it into a value of `GraphQLRequest`.
 ///   - When `content-type` is `application/json`, the body is parsed as a JSON object which
 ///     contains a GraphQL query and supplemental fields if needed.
 ///   - When `content-type` is `application/graphql`, the body is parsed as a single GraphQL query.
+///
+/// If the request has the header `X-Apollo-Tracing: 1`, it is recorded in the returned request
+/// (see [`GraphQLRequest::is_tracing_requested`]).  The header does not enable the tracing
+/// extension by itself.
+///
+/// [`skip_unsupported_methods`]: ./struct.GraphQLRequestEndpoint.html#method.skip_unsupported_methods
+/// [`GraphQLRequest::is_tracing_requested`]: ./struct.GraphQLRequest.html#method.is_tracing_requested
 pub fn graphql_request() -> GraphQLRequestEndpoint {
-    GraphQLRequestEndpoint { _priv: () }
+    GraphQLRequestEndpoint {
+        csrf_prevention: None,
+        skip_unsupported_methods: false,
+    }
 }
 
 #[allow(missing_docs)]
 #[derive(Debug)]
 pub struct GraphQLRequestEndpoint {
-    _priv: (),
+    csrf_prevention: Option<CsrfPrevention>,
+    skip_unsupported_methods: bool,
+}
+
+impl GraphQLRequestEndpoint {
+    /// Rejects the requests which can be sent from other origins without a CORS preflight.
+    ///
+    /// See [`CsrfPrevention`] for details.
+    ///
+    /// [`CsrfPrevention`]: ./struct.CsrfPrevention.html
+    pub fn csrf_prevention(self, csrf_prevention: CsrfPrevention) -> GraphQLRequestEndpoint {
+        GraphQLRequestEndpoint {
+            csrf_prevention: Some(csrf_prevention),
+            ..self
+        }
+    }
+
+    /// Specifies whether to skip the requests with a method other than GET, HEAD and POST,
+    /// so that the other endpoints combined with `or` can handle them.
+    ///
+    /// By default, such requests are rejected with `405 Method Not Allowed` and
+    /// the header `Allow: GET, HEAD, POST`.
+    pub fn skip_unsupported_methods(self, skip: bool) -> GraphQLRequestEndpoint {
+        GraphQLRequestEndpoint {
+            skip_unsupported_methods: skip,
+            ..self
+        }
+    }
+}
+
+#[derive(Debug)]
+struct MethodNotAllowed;
+
+impl fmt::Display for MethodNotAllowed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("method not allowed")
+    }
+}
+
+impl HttpError for MethodNotAllowed {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::METHOD_NOT_ALLOWED
+    }
+
+    fn headers(&self, headers: &mut HeaderMap) {
+        headers.insert(header::ALLOW, HeaderValue::from_static("GET, HEAD, POST"));
+    }
 }
 
 impl<'a> Endpoint<'a> for GraphQLRequestEndpoint {
@@ -47,13 +121,41 @@ impl<'a> Endpoint<'a> for GraphQLRequestEndpoint {
     type Future = RequestFuture<'a>;
 
     fn apply(&'a self, cx: &mut ApplyContext<'_>) -> ApplyResult<Self::Future> {
-        if cx.input().method() == Method::GET {
+        let is_post = match *cx.input().method() {
+            Method::GET | Method::HEAD => false,
+            Method::POST => true,
+            _ if self.skip_unsupported_methods => return Err(ApplyError::not_matched()),
+            // Reported from the future, since `ApplyError` drops the `Allow` header.
+            _ => {
+                return Ok(RequestFuture {
+                    kind: RequestKind::MethodNotAllowed,
+                    tracing_requested: false,
+                })
+            }
+        };
+        if let Some(ref csrf_prevention) = self.csrf_prevention {
+            if !csrf_prevention.is_preflighted(cx.input().headers()) {
+                return Err(ApplyError::custom(error::bad_request(
+                    "the request has been blocked as a potential cross-site request forgery; \
+                     specify a content-type other than the simple ones, or a header such as \
+                     `X-GraphQL-CSRF`",
+                )));
+            }
+        }
+        let tracing_requested = cx
+            .input()
+            .headers()
+            .get("x-apollo-tracing")
+            .map_or(false, |value| value == "1");
+        if is_post {
             Ok(RequestFuture {
-                kind: RequestKind::Get,
+                kind: RequestKind::Post(body::receive_all().apply(cx)?),
+                tracing_requested,
             })
         } else {
             Ok(RequestFuture {
-                kind: RequestKind::Post(body::receive_all().apply(cx)?),
+                kind: RequestKind::Get,
+                tracing_requested,
             })
         }
     }
@@ -63,12 +165,25 @@ impl<'a> Endpoint<'a> for GraphQLRequestEndpoint {
 #[derive(Debug)]
 pub struct RequestFuture<'a> {
     kind: RequestKind<'a>,
+    tracing_requested: bool,
+}
+
+impl<'a> RequestFuture<'a> {
+    // Returns the error if the request has been rejected without reading it,
+    // so that it can be reported before evaluating the other endpoints.
+    pub(crate) fn rejection(&self) -> Option<Error> {
+        match self.kind {
+            RequestKind::MethodNotAllowed => Some(MethodNotAllowed.into()),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug)]
 enum RequestKind<'a> {
     Get,
     Post(<body::ReceiveAll as Endpoint<'a>>::Future),
+    MethodNotAllowed,
 }
 
 impl<'a> Future for RequestFuture<'a> {
@@ -84,6 +199,7 @@ impl<'a> Future for RequestFuture<'a> {
                     .ok_or_else(|| error::bad_request("missing query string"))?;
                 parse_query_str(s)
             }),
+            RequestKind::MethodNotAllowed => Err(MethodNotAllowed.into()),
             RequestKind::Post(ref mut f) => {
                 let (data,) = try_ready!(f.poll());
                 with_get_cx(
@@ -103,32 +219,352 @@ impl<'a> Future for RequestFuture<'a> {
             }
         };
 
-        result.map(|request| (request,).into())
+        let tracing_requested = self.tracing_requested;
+        let conditional = match self.kind {
+            RequestKind::Get => true,
+            _ => false,
+        };
+        result.map(|request: GraphQLRequest| {
+            (GraphQLRequest {
+                tracing_requested,
+                conditional,
+                ..request
+            },)
+                .into()
+        })
     }
 }
 
 // ==== GraphQLRequest ====
 
 /// A type representing the decoded GraphQL query obtained by parsing an HTTP request.
-#[derive(Debug, Deserialize)]
-pub struct GraphQLRequest(GraphQLRequestKind);
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct GraphQLRequest {
+    kind: GraphQLRequestKind,
+    #[serde(skip)]
+    tracing: bool,
+    #[serde(skip)]
+    tracing_requested: bool,
+    // Whether the request has been sent by GET or HEAD, i.e. the conditional request
+    // is available for the response.
+    #[serde(skip)]
+    conditional: bool,
+    #[serde(skip)]
+    access_log: Option<AccessLog>,
+    #[serde(skip)]
+    slow_operation_log: Option<Arc<SlowOperationLog>>,
+    #[serde(skip)]
+    metrics: Option<Arc<Metrics>>,
+    #[serde(skip)]
+    field_usage: Option<Arc<FieldUsage>>,
+    #[serde(skip)]
+    request_id: Option<RequestId>,
+    #[serde(skip)]
+    _permit: Option<Permit>,
+}
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 #[serde(untagged)]
 enum GraphQLRequestKind {
-    Single(juniper::http::GraphQLRequest),
-    Batch(Vec<juniper::http::GraphQLRequest>),
+    Single(SingleRequest),
+    Batch(Vec<SingleRequest>),
+}
+
+// The same structure as `juniper::http::GraphQLRequest`, whose fields are not accessible.
+#[derive(Debug, Serialize)]
+pub(crate) struct SingleRequest {
+    query: String,
+    #[serde(rename = "operationName")]
+    operation_name: Option<String>,
+    variables: Option<InputValue>,
+    // The query is scanned once when the request is built, and the result is shared
+    // by the cache, the authorization, the logs and the metrics.
+    #[serde(skip)]
+    document: Option<Box<Document>>,
+}
+
+impl<'de> Deserialize<'de> for SingleRequest {
+    fn deserialize<D>(deserializer: D) -> Result<SingleRequest, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct Raw {
+            query: String,
+            #[serde(rename = "operationName")]
+            operation_name: Option<String>,
+            variables: Option<InputValue>,
+        }
+
+        let raw = Raw::deserialize(deserializer)?;
+        Ok(SingleRequest::new(
+            raw.query,
+            raw.operation_name,
+            raw.variables,
+        ))
+    }
+}
+
+impl SingleRequest {
+    pub(crate) fn new(
+        query: String,
+        operation_name: Option<String>,
+        variables: Option<InputValue>,
+    ) -> SingleRequest {
+        let document = Document::parse(&query).map(Box::new);
+        SingleRequest {
+            query,
+            operation_name,
+            variables,
+            document,
+        }
+    }
+
+    pub(crate) fn query(&self) -> &str {
+        &self.query
+    }
+
+    // Returns the summary of the query, or `None` if it could not be scanned.
+    pub(crate) fn document(&self) -> Option<&Document> {
+        self.document.as_ref().map(Box::as_ref)
+    }
+
+    pub(crate) fn operation_name(&self) -> Option<&str> {
+        self.operation_name.as_ref().map(String::as_str)
+    }
+
+    pub(crate) fn raw_variables(&self) -> Option<&InputValue> {
+        self.variables.as_ref()
+    }
+
+    fn operation_kind(&self) -> Option<OperationKind> {
+        let operation = self
+            .document()?
+            .operation(self.operation_name.as_ref().map(String::as_str))?;
+        Some(operation.kind())
+    }
+
+    fn variables(&self) -> Variables {
+        self.variables
+            .as_ref()
+            .and_then(|variables| {
+                variables.to_object_value().map(|object| {
+                    object
+                        .into_iter()
+                        .map(|(k, v)| (k.to_owned(), v.clone()))
+                        .collect()
+                })
+            })
+            .unwrap_or_default()
+    }
+
+    // Executes the query and returns whether the execution has been started,
+    // along with the response object in the same form as `juniper::http::GraphQLResponse`.
+    fn execute<QueryT, MutationT, CtxT>(
+        &self,
+        root_node: &RootNode<'static, QueryT, MutationT>,
+        context: &CtxT,
+        tracing: bool,
+    ) -> (bool, serde_json::Value)
+    where
+        QueryT: GraphQLType<Context = CtxT>,
+        MutationT: GraphQLType<Context = CtxT>,
+    {
+        let variables = self.variables();
+        let execute = || {
+            juniper::execute(
+                &self.query,
+                self.operation_name.as_ref().map(String::as_str),
+                root_node,
+                &variables,
+                context,
+            )
+        };
+        let (result, tracing) = if tracing {
+            let (result, tracing) = trace(execute);
+            (result, Some(tracing))
+        } else {
+            (execute(), None)
+        };
+        match result {
+            Ok((data, errors)) => {
+                let mut response = json!({ "data": data });
+                if !errors.is_empty() {
+                    response["errors"] = json!(errors);
+                }
+                if let Some(tracing) = tracing {
+                    response["extensions"] = json!({ "tracing": tracing });
+                }
+                (true, response)
+            }
+            Err(err) => (false, json!({ "errors": err })),
+        }
+    }
 }
 
 impl GraphQLRequest {
-    fn single(
+    /// Creates a `GraphQLRequest` which contains a single GraphQL query.
+    pub fn single(
         query: String,
         operation_name: Option<String>,
         variables: Option<InputValue>,
     ) -> GraphQLRequest {
-        GraphQLRequest(GraphQLRequestKind::Single(
-            juniper::http::GraphQLRequest::new(query, operation_name, variables),
-        ))
+        GraphQLRequest {
+            kind: GraphQLRequestKind::Single(SingleRequest::new(query, operation_name, variables)),
+            tracing: false,
+            tracing_requested: false,
+            conditional: false,
+            access_log: None,
+            slow_operation_log: None,
+            metrics: None,
+            field_usage: None,
+            request_id: None,
+            _permit: None,
+        }
+    }
+
+    /// Specifies whether to attach the timings of execution to the response, under
+    /// `extensions.tracing` in the Apollo tracing format.
+    ///
+    /// See also [`Traced`] for recording the resolvers.
+    ///
+    /// [`Traced`]: ../execute/struct.Traced.html
+    pub fn with_tracing(self, tracing: bool) -> GraphQLRequest {
+        GraphQLRequest { tracing, ..self }
+    }
+
+    /// Returns `true` if the tracing extension is enabled for this request.
+    pub fn is_tracing(&self) -> bool {
+        self.tracing
+    }
+
+    /// Returns `true` if the client has asked for the tracing extension by adding the header
+    /// `X-Apollo-Tracing: 1` to the request.
+    ///
+    /// The executors honour it only when configured with [`Custom::tracing`].
+    ///
+    /// [`Custom::tracing`]: ../execute/struct.Custom.html#method.tracing
+    pub fn is_tracing_requested(&self) -> bool {
+        self.tracing_requested
+    }
+
+    /// Enables the access log of the operations in this request with the specified configuration.
+    ///
+    /// See [`AccessLog`] for details.
+    ///
+    /// [`AccessLog`]: ../execute/struct.AccessLog.html
+    pub fn with_access_log(self, access_log: AccessLog) -> GraphQLRequest {
+        GraphQLRequest {
+            access_log: Some(access_log),
+            ..self
+        }
+    }
+
+    /// Enables the report of slow operations in this request.
+    ///
+    /// See [`SlowOperationLog`] for details.
+    ///
+    /// [`SlowOperationLog`]: ../execute/struct.SlowOperationLog.html
+    pub fn with_slow_operation_log(
+        self,
+        slow_operation_log: Arc<SlowOperationLog>,
+    ) -> GraphQLRequest {
+        GraphQLRequest {
+            slow_operation_log: Some(slow_operation_log),
+            ..self
+        }
+    }
+
+    /// Records the metrics of the operations in this request to the specified registry.
+    pub fn with_metrics(self, metrics: Arc<Metrics>) -> GraphQLRequest {
+        GraphQLRequest {
+            metrics: Some(metrics),
+            ..self
+        }
+    }
+
+    /// Records the usage statistics of the fields selected by this request.
+    pub fn with_field_usage(self, field_usage: Arc<FieldUsage>) -> GraphQLRequest {
+        GraphQLRequest {
+            field_usage: Some(field_usage),
+            ..self
+        }
+    }
+
+    /// Associates the identifier of the HTTP request, which is included in the log records.
+    pub fn with_request_id(self, request_id: RequestId) -> GraphQLRequest {
+        GraphQLRequest {
+            request_id: Some(request_id),
+            ..self
+        }
+    }
+
+    // The permit is held by the task which owns this request, and released when the task
+    // finishes or is discarded before execution.
+    pub(crate) fn with_permit(self, permit: Permit) -> GraphQLRequest {
+        GraphQLRequest {
+            _permit: Some(permit),
+            ..self
+        }
+    }
+
+    pub(crate) fn requests(&self) -> &[SingleRequest] {
+        match self.kind {
+            GraphQLRequestKind::Single(ref request) => slice::from_ref(request),
+            GraphQLRequestKind::Batch(ref requests) => requests,
+        }
+    }
+
+    pub(crate) fn requests_mut(&mut self) -> &mut [SingleRequest] {
+        match self.kind {
+            GraphQLRequestKind::Single(ref mut request) => slice::from_mut(request),
+            GraphQLRequestKind::Batch(ref mut requests) => requests,
+        }
+    }
+
+    // Returns `true` if the response to this request may be answered with `304 Not Modified`.
+    // The operations are inspected only when the request has been sent by GET or HEAD.
+    pub(crate) fn is_conditional(&self) -> bool {
+        self.conditional && self.is_query()
+    }
+
+    /// Returns `true` if all operations in this request are determined as queries.
+    pub(crate) fn is_query(&self) -> bool {
+        self.requests()
+            .iter()
+            .all(|request| request.operation_kind() == Some(OperationKind::Query))
+    }
+
+    // Records the metrics and the access log of the response served without executing
+    // this request, e.g. from the response cache or the execution started by an identical request.
+    pub(crate) fn observe_replayed(&self, response: &GraphQLResponse) {
+        let status = response.status();
+        let observe = |request: &SingleRequest, batch_index, body: &serde_json::Value| {
+            if let Some(ref metrics) = self.metrics {
+                metrics.observe_operation(request, None, body, status.is_success());
+            }
+            if let Some(ref access_log) = self.access_log {
+                access_log.log(
+                    request,
+                    batch_index,
+                    None,
+                    body,
+                    status,
+                    self.request_id.as_ref(),
+                );
+            }
+        };
+        match self.kind {
+            GraphQLRequestKind::Single(ref request) => observe(request, None, &response.body),
+            GraphQLRequestKind::Batch(ref requests) => {
+                if let serde_json::Value::Array(ref bodies) = response.body {
+                    for (i, (request, body)) in requests.iter().zip(bodies).enumerate() {
+                        observe(request, Some(i), body);
+                    }
+                }
+            }
+        }
     }
 
     /// Executes a GraphQL query represented by this value using the specified schema and context.
@@ -142,28 +578,128 @@ impl GraphQLRequest {
         MutationT: GraphQLType<Context = CtxT>,
     {
         use self::GraphQLRequestKind::*;
-        match self.0 {
+        match self.kind {
             Single(ref request) => {
-                let response = request.execute(root_node, context);
+                let (is_ok, body) = self.execute_operation(request, None, root_node, context);
                 GraphQLResponse {
-                    is_ok: response.is_ok(),
-                    body: serde_json::to_vec(&response),
+                    status: status_code(is_ok),
+                    body,
+                    cache_hint: None,
+                    conditional: self.is_conditional(),
+                    compression: None,
+                    pretty: false,
+                    headers: HeaderMap::new(),
                 }
             }
             Batch(ref requests) => {
-                let responses: Vec<_> = requests
-                    .iter()
-                    .map(|request| request.execute(root_node, context))
-                    .collect();
+                let (is_ok, body) =
+                    requests
+                        .iter()
+                        .fold((true, vec![]), |(all_ok, mut responses), request| {
+                            let index = responses.len();
+                            let (is_ok, response) =
+                                self.execute_operation(request, Some(index), root_node, context);
+                            responses.push(response);
+                            (all_ok && is_ok, responses)
+                        });
                 GraphQLResponse {
-                    is_ok: responses.iter().all(|response| response.is_ok()),
-                    body: serde_json::to_vec(&responses),
+                    status: status_code(is_ok),
+                    body: serde_json::Value::Array(body),
+                    cache_hint: None,
+                    conditional: self.is_conditional(),
+                    compression: None,
+                    pretty: false,
+                    headers: HeaderMap::new(),
                 }
             }
         }
     }
 }
 
+impl GraphQLRequest {
+    fn execute_operation<QueryT, MutationT, CtxT>(
+        &self,
+        request: &SingleRequest,
+        batch_index: Option<usize>,
+        root_node: &RootNode<'static, QueryT, MutationT>,
+        context: &CtxT,
+    ) -> (bool, serde_json::Value)
+    where
+        QueryT: GraphQLType<Context = CtxT>,
+        MutationT: GraphQLType<Context = CtxT>,
+    {
+        let start = Instant::now();
+        let (is_ok, response) = {
+            let _guard = PanicGuard {
+                request: self,
+                single: request,
+                batch_index,
+                start,
+            };
+            request.execute(root_node, context, self.tracing)
+        };
+        let duration = start.elapsed();
+        if let Some(ref slow_operation_log) = self.slow_operation_log {
+            slow_operation_log.observe(request, duration, self.request_id.as_ref());
+        }
+        if let Some(ref metrics) = self.metrics {
+            metrics.observe_operation(request, Some(duration), &response, is_ok);
+        }
+        if let Some(ref field_usage) = self.field_usage {
+            if is_ok {
+                field_usage.record(request, root_node);
+            }
+        }
+        if let Some(ref access_log) = self.access_log {
+            access_log.log(
+                request,
+                batch_index,
+                Some(duration),
+                &response,
+                status_code(is_ok),
+                self.request_id.as_ref(),
+            );
+        }
+        (is_ok, response)
+    }
+}
+
+// Records the operation in the access log when a resolver panics, since the response is
+// produced by the panic isolation of the executors without returning to `execute_operation`.
+struct PanicGuard<'a> {
+    request: &'a GraphQLRequest,
+    single: &'a SingleRequest,
+    batch_index: Option<usize>,
+    start: Instant,
+}
+
+impl<'a> Drop for PanicGuard<'a> {
+    fn drop(&mut self) {
+        if !thread::panicking() {
+            return;
+        }
+        if let Some(ref access_log) = self.request.access_log {
+            let response = GraphQLResponse::internal_server_error();
+            access_log.log(
+                self.single,
+                self.batch_index,
+                Some(self.start.elapsed()),
+                &response.body,
+                response.status,
+                self.request.request_id.as_ref(),
+            );
+        }
+    }
+}
+
+fn status_code(is_ok: bool) -> StatusCode {
+    if is_ok {
+        StatusCode::OK
+    } else {
+        StatusCode::BAD_REQUEST
+    }
+}
+
 fn parse_query_str(s: &str) -> Result<GraphQLRequest, Error> {
     #[derive(Debug, Deserialize)]
     struct ParsedQuery {
@@ -206,47 +742,295 @@ fn parse_query_str(s: &str) -> Result<GraphQLRequest, Error> {
 }
 
 /// A type representing the result from executing a GraphQL query.
-#[derive(Debug)]
+///
+/// When the successful result of queries is returned for a `GET` request,
+/// the response has an `ETag` header and the request with the matching `If-None-Match`
+/// header is answered with `304 Not Modified`.
+#[derive(Debug, Clone)]
 pub struct GraphQLResponse {
-    is_ok: bool,
-    body: Result<Vec<u8>, serde_json::Error>,
+    status: StatusCode,
+    body: serde_json::Value,
+    cache_hint: Option<CacheHint>,
+    conditional: bool,
+    compression: Option<Compression>,
+    pretty: bool,
+    headers: HeaderMap,
+}
+
+impl GraphQLResponse {
+    /// Returns the HTTP status code of this response.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// Returns the cache policy attached to this response.
+    pub fn cache_hint(&self) -> Option<CacheHint> {
+        self.cache_hint
+    }
+
+    pub(crate) fn set_cache_hint(&mut self, hint: CacheHint) {
+        self.cache_hint = Some(hint);
+    }
+
+    pub(crate) fn set_conditional(&mut self, conditional: bool) {
+        self.conditional = conditional;
+    }
+
+    /// Enables the compression of the response body with the specified configuration.
+    pub fn with_compression(self, compression: Compression) -> GraphQLResponse {
+        GraphQLResponse {
+            compression: Some(compression),
+            ..self
+        }
+    }
+
+    /// Specifies whether to pretty-print the response body.
+    ///
+    /// In debug builds, the client can also request the pretty-printed body by adding
+    /// the query parameter `pretty` or the header `X-Pretty` to the request.
+    pub fn with_pretty(self, pretty: bool) -> GraphQLResponse {
+        GraphQLResponse { pretty, ..self }
+    }
+
+    /// Adds an entry to the `extensions` of the response, or of each response in a batch.
+    pub(crate) fn set_extension(&mut self, key: &str, value: serde_json::Value) {
+        let responses = match self.body {
+            serde_json::Value::Array(ref mut responses) => responses.iter_mut().collect(),
+            ref mut response => vec![response],
+        };
+        for response in responses {
+            if let Some(response) = response.as_object_mut() {
+                let extensions = response.entry("extensions").or_insert_with(|| json!({}));
+                if let Some(extensions) = extensions.as_object_mut() {
+                    extensions.insert(key.to_owned(), value.clone());
+                }
+            }
+        }
+    }
+
+    /// Returns `true` if the response contains any errors, including the field errors.
+    pub fn has_errors(&self) -> bool {
+        match self.body {
+            serde_json::Value::Array(ref responses) => responses
+                .iter()
+                .any(|response| response.get("errors").is_some()),
+            ref response => response.get("errors").is_some(),
+        }
+    }
+
+    /// Creates a response which reports an unexpected failure during execution,
+    /// without exposing its details to the client.
+    pub(crate) fn internal_server_error() -> GraphQLResponse {
+        GraphQLResponse::error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Internal server error",
+            "INTERNAL_SERVER_ERROR",
+        )
+    }
+
+    /// Creates a response which reports an error occurred before the execution.
+    pub(crate) fn error(status: StatusCode, message: &str, code: &str) -> GraphQLResponse {
+        let response = json!({
+            "data": null,
+            "errors": [{
+                "message": message,
+                "extensions": { "code": code },
+            }],
+        });
+        GraphQLResponse {
+            status,
+            body: response,
+            cache_hint: None,
+            conditional: false,
+            compression: None,
+            pretty: false,
+            headers: HeaderMap::new(),
+        }
+    }
+
+    /// Creates a response which reports an error occurred before the execution
+    /// of the specified request, in the same shape as the response to it.
+    pub(crate) fn error_for(
+        request: &GraphQLRequest,
+        status: StatusCode,
+        message: &str,
+        code: &str,
+    ) -> GraphQLResponse {
+        let mut response = GraphQLResponse::error(status, message, code);
+        if let GraphQLRequestKind::Batch(ref requests) = request.kind {
+            response.body = serde_json::Value::Array(vec![response.body; requests.len()]);
+        }
+        response
+    }
+
+    /// Returns a mutable reference to the additional headers of the response.
+    pub(crate) fn headers_mut(&mut self) -> &mut HeaderMap {
+        &mut self.headers
+    }
 }
 
 impl Output for GraphQLResponse {
     type Body = Vec<u8>;
     type Error = Error;
 
-    fn respond(self, _: &mut OutputContext<'_>) -> Result<Response<Self::Body>, Self::Error> {
-        let status = if self.is_ok {
-            StatusCode::OK
+    fn respond(self, cx: &mut OutputContext<'_>) -> Result<Response<Self::Body>, Self::Error> {
+        let pretty = self.pretty || (cfg!(debug_assertions) && is_pretty_requested(cx.input()));
+        let body = if pretty {
+            serde_json::to_vec_pretty(&self.body).map_err(error::fail)?
         } else {
-            StatusCode::BAD_REQUEST
+            serde_json::to_vec(&self.body).map_err(error::fail)?
+        };
+
+        let mut response = Response::builder();
+        for (name, value) in &self.headers {
+            response.header(name, value.clone());
+        }
+        if let Some(hint) = self.cache_hint {
+            let scope = match hint.scope() {
+                CacheScope::Public => "public",
+                CacheScope::Private => "private",
+            };
+            response.header(
+                header::CACHE_CONTROL,
+                format!("max-age={}, {}", hint.max_age(), scope).as_str(),
+            );
+        }
+
+        let encoding = self.compression.and_then(|compression| {
+            response.header(header::VARY, "accept-encoding");
+            compression.negotiate(cx.input().headers(), body.len())
+        });
+
+        // The conditional requests are supported only for the queries sent by GET (or HEAD).
+        let is_head = *cx.input().method() == Method::HEAD;
+        let is_get = is_head || *cx.input().method() == Method::GET;
+        if self.conditional && self.status.is_success() && is_get {
+            let mut etag = entity_tag(&body);
+            if let Some(encoding) = encoding {
+                // Each representation must have a distinct strong entity tag.
+                etag.insert_str(etag.len() - 1, &format!("-{}", encoding.as_str()));
+            }
+            response.header(header::ETAG, etag.as_str());
+            if is_not_modified(cx.input().headers(), &etag) {
+                return Ok(response
+                    .status(StatusCode::NOT_MODIFIED)
+                    .body(vec![])
+                    .expect("should be a valid response"));
+            }
+        }
+
+        let body = match (self.compression, encoding) {
+            (Some(compression), Some(encoding)) => {
+                response.header(header::CONTENT_ENCODING, encoding.as_str());
+                compression.encode(encoding, &body).map_err(error::fail)?
+            }
+            _ => body,
         };
-        let body = self.body.map_err(error::fail)?;
-        Ok(Response::builder()
-            .status(status)
+
+        // The response to a HEAD request has the same headers as GET, without the body.
+        let body = if is_head { vec![] } else { body };
+
+        Ok(response
+            .status(self.status)
             .header(header::CONTENT_TYPE, "application/json")
             .body(body)
             .expect("should be a valid response"))
     }
 }
 
+// Returns `true` if the request has the query parameter `pretty` or the header `X-Pretty`,
+// whose value is not `false` or `0`.
+fn is_pretty_requested(input: &Input) -> bool {
+    let is_enabled = |value: &str| value != "false" && value != "0";
+    let query = input.uri().query().unwrap_or("");
+    let in_query = query.split('&').any(|param| {
+        let mut pair = param.splitn(2, '=');
+        pair.next() == Some("pretty") && pair.next().map_or(true, is_enabled)
+    });
+    let in_header = input
+        .headers()
+        .get("x-pretty")
+        .and_then(|value| value.to_str().ok())
+        .map_or(false, |value| is_enabled(value.trim()));
+    in_query || in_header
+}
+
+// Computes a strong entity tag from the serialized response body.
+fn entity_tag(body: &[u8]) -> String {
+    format!("\"{:x}-{:016x}\"", body.len(), fnv1a(body))
+}
+
+// Computes the 64-bit FNV-1a hash of the bytes.
+//
+// It is used for the values which must not change across builds, rather than `DefaultHasher`
+// whose algorithm is unspecified.
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325_u64, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+// Evaluates the `If-None-Match` header with the weak comparison, as described in RFC 7232.
+fn is_not_modified(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|tag| tag.trim())
+        .any(|tag| tag == "*" || tag == etag || (tag.starts_with("W/") && tag[2..] == *etag))
+}
+
 #[cfg(test)]
 mod tests {
+    use finchers::prelude::*;
     use finchers::test;
-    use http::Request;
+    use http::request::Builder;
+    use http::{Request, StatusCode};
+    use serde_json;
 
-    use super::{graphql_request, GraphQLRequest, GraphQLRequestKind};
+    use super::{entity_tag, graphql_request, CsrfPrevention, GraphQLRequest, GraphQLRequestKind};
+    use testing::execute_request;
 
     #[test]
     fn test_get_request() {
         let mut runner = test::runner(graphql_request());
         assert_matches!(
             runner.apply(Request::get("/?query={{}}")),
-            Ok(GraphQLRequest(GraphQLRequestKind::Single(..)))
+            Ok(GraphQLRequest {
+                kind: GraphQLRequestKind::Single(..),
+                ..
+            })
         );
     }
 
+    #[test]
+    fn test_csrf_prevention() {
+        let mut runner = test::runner(graphql_request().csrf_prevention(CsrfPrevention::new()));
+        let err = runner.apply(Request::get("/?query={{}}")).unwrap_err();
+        assert_eq!(err.status_code(), StatusCode::BAD_REQUEST);
+        assert_matches!(
+            runner.apply(Request::get("/?query={{}}").header("x-graphql-csrf", "1")),
+            Ok(..)
+        );
+        assert_matches!(
+            runner.apply(
+                Request::post("/")
+                    .header("content-type", "application/json")
+                    .body(r#"{"query":"{}"}"#)
+            ),
+            Ok(..)
+        );
+        let err = runner
+            .apply(
+                Request::post("/")
+                    .header("content-type", "text/plain")
+                    .body(r#"{"query":"{}"}"#),
+            )
+            .unwrap_err();
+        assert_eq!(err.status_code(), StatusCode::BAD_REQUEST);
+    }
+
     #[test]
     fn test_json_request() {
         let mut runner = test::runner(graphql_request());
@@ -256,7 +1040,10 @@ mod tests {
                     .header("content-type", "application/json")
                     .body(r#"{ "query": "{ apiVersion }" }"#),
             ),
-            Ok(GraphQLRequest(GraphQLRequestKind::Single(..)))
+            Ok(GraphQLRequest {
+                kind: GraphQLRequestKind::Single(..),
+                ..
+            })
         );
     }
 
@@ -274,10 +1061,25 @@ mod tests {
                     ]"#,
                     ),
             ),
-            Ok(GraphQLRequest(GraphQLRequestKind::Batch(..)))
+            Ok(GraphQLRequest {
+                kind: GraphQLRequestKind::Batch(..),
+                ..
+            })
         );
     }
 
+    #[test]
+    fn test_document_parsed_on_build() {
+        let request: GraphQLRequest =
+            serde_json::from_str(r#"[{ "query": "{ apiVersion }" }, { "query": "{" }]"#).unwrap();
+        let requests = request.requests();
+        assert!(requests[0].document().is_some());
+        assert!(requests[1].document().is_none());
+
+        let request = GraphQLRequest::single("query Q { me { id } }".into(), None, None);
+        assert!(request.requests()[0].document().is_some());
+    }
+
     #[test]
     fn test_graphql_request() {
         let mut runner = test::runner(graphql_request());
@@ -287,7 +1089,119 @@ mod tests {
                     .header("content-type", "application/graphql")
                     .body(r#"{ apiVersion }"#),
             ),
-            Ok(GraphQLRequest(GraphQLRequestKind::Single(..)))
+            Ok(GraphQLRequest {
+                kind: GraphQLRequestKind::Single(..),
+                ..
+            })
+        );
+    }
+
+    #[test]
+    fn test_conditional_get() {
+        let mut runner = test::runner(execute_request());
+
+        let response = runner
+            .perform(Request::get("/?query={hero{name}}"))
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let etag = response.headers().get("etag").cloned().unwrap();
+
+        let response = runner
+            .perform(Request::get("/?query={hero{name}}").header("if-none-match", etag.clone()))
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers().get("etag"), Some(&etag));
+
+        let response = runner
+            .perform(Request::get("/?query={hero{id}}").header("if-none-match", etag))
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = runner
+            .perform(
+                Request::post("/")
+                    .header("content-type", "application/graphql")
+                    .body("{ hero { name } }"),
+            )
+            .unwrap();
+        assert!(response.headers().get("etag").is_none());
+    }
+
+    #[test]
+    fn test_entity_tag() {
+        assert_eq!(entity_tag(b""), "\"0-cbf29ce484222325\"");
+        assert_eq!(entity_tag(b"a"), "\"1-af63dc4c8601ec8c\"");
+    }
+
+    #[test]
+    fn test_method_filtering() {
+        let mut runner = test::runner(graphql_request());
+        let err = runner.apply(Request::put("/?query={{}}")).unwrap_err();
+        assert_eq!(err.status_code(), StatusCode::METHOD_NOT_ALLOWED);
+        let mut headers = Default::default();
+        err.headers(&mut headers);
+        assert_eq!(headers["allow"], "GET, HEAD, POST");
+
+        let mut runner = test::runner(
+            graphql_request()
+                .skip_unsupported_methods(true)
+                .map(|_: GraphQLRequest| "graphql")
+                .or(endpoint::unit().map(|| "fallback")),
         );
+        let response = runner.perform(Request::delete("/")).unwrap();
+        assert_eq!(response.body().to_utf8().unwrap(), "fallback");
+    }
+
+    #[test]
+    fn test_head_request() {
+        let mut runner = test::runner(execute_request());
+
+        let response = runner
+            .perform(Request::head("/?query={hero{name}}"))
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.body().to_utf8().unwrap().is_empty());
+        let etag = response.headers().get("etag").cloned().unwrap();
+
+        let response = runner
+            .perform(Request::get("/?query={hero{name}}"))
+            .unwrap();
+        assert_eq!(response.headers().get("etag"), Some(&etag));
+        assert!(!response.body().to_utf8().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_pretty_response() {
+        let mut runner = test::runner(execute_request());
+
+        let mut is_pretty = |request: &mut Builder| {
+            let response = runner.perform(request.body(())).unwrap();
+            response.body().to_utf8().unwrap().contains('\n')
+        };
+
+        assert!(!is_pretty(
+            Request::get("/?query={hero{name}}").header("x-pretty", "0")
+        ));
+        assert!(is_pretty(
+            Request::get("/?query={hero{name}}").header("x-pretty", "1")
+        ));
+        assert!(is_pretty(&mut Request::get("/?query={hero{name}}&pretty")));
+        assert!(!is_pretty(&mut Request::get(
+            "/?query={hero{name}}&pretty=false"
+        )));
+    }
+
+    #[test]
+    fn test_tracing_header() {
+        let mut runner = test::runner(graphql_request());
+
+        let request = runner
+            .apply(Request::get("/?query={hero{name}}").header("x-apollo-tracing", "1"))
+            .unwrap();
+        assert!(request.is_tracing_requested());
+        assert!(!request.is_tracing());
+
+        let request = runner.apply(Request::get("/?query={hero{name}}")).unwrap();
+        assert!(!request.is_tracing_requested());
     }
 }
@@ -0,0 +1,196 @@
+//! Propagation of the identifier of HTTP requests.
+//!
+//! The endpoint created by [`request_id`] takes the identifier from the `X-Request-Id` header
+//! of the request, or generates a new one if it is missing or malformed. The identifier is
+//! echoed back in the `X-Request-Id` header of the response.
+//!
+//! When the endpoint is used for constructing the context, the executors pick up the identifier,
+//! include it in the access log and the report of slow operations, and attach it to the response
+//! under `extensions.requestId`.
+//!
+//! # Example
+//!
+//! ```
+//! # #[macro_use]
+//! # extern crate finchers;
+//! # extern crate finchers_juniper;
+//! # extern crate juniper;
+//! # use finchers::prelude::*;
+//! # use finchers_juniper::request_id::{request_id, RequestId};
+//! # use juniper::tests::model::Database;
+//! # use juniper::{EmptyMutation, RootNode};
+//! # fn main() {
+//! # let schema = RootNode::new(Database::new(), EmptyMutation::<Database>::new());
+//! let fetch_graphql_context = request_id().map(|id: RequestId| {
+//!     // The identifier can be stored in the context.
+//! #   drop(id);
+//!     Database::new()
+//! });
+//!
+//! let endpoint = path!(/ "graphql" /)
+//!     .and(fetch_graphql_context)
+//!     .wrap(finchers_juniper::execute::nonblocking(schema));
+//! # drop(endpoint);
+//! # }
+//! ```
+//!
+//! [`request_id`]: ./fn.request_id.html
+
+use finchers::endpoint::{ApplyContext, ApplyResult, Endpoint};
+use finchers::error::Error;
+use finchers::input::Input;
+
+use futures::future;
+
+use http::header::{HeaderName, HeaderValue};
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::SystemTime;
+
+/// The maximum length of the identifiers accepted from the clients.
+const MAX_LEN: usize = 128;
+
+fn header_name() -> HeaderName {
+    HeaderName::from_static("x-request-id")
+}
+
+/// The identifier of an HTTP request.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RequestId(String);
+
+impl RequestId {
+    /// Generates a new random identifier.
+    pub fn generate() -> RequestId {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let state = RandomState::new();
+        let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+        let now = SystemTime::now();
+        let mut hashes = (0..2u8).map(|i| {
+            let mut hasher = state.build_hasher();
+            (i, count, now).hash(&mut hasher);
+            hasher.finish()
+        });
+        RequestId(format!(
+            "{:016x}{:016x}",
+            hashes.next().unwrap(),
+            hashes.next().unwrap()
+        ))
+    }
+
+    /// Returns the identifier as a string slice.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    // Accepts only the identifiers which can be written in the log records as they are.
+    fn parse(value: &HeaderValue) -> Option<RequestId> {
+        let value = value.to_str().ok()?;
+        let is_valid = !value.is_empty()
+            && value.len() <= MAX_LEN
+            && value
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b"-_.:".contains(&b));
+        if is_valid {
+            Some(RequestId(value.to_owned()))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the identifier assigned to the current request by the endpoint `request_id`.
+    pub(crate) fn from_input(input: &mut Input) -> Option<RequestId> {
+        input
+            .response_headers()
+            .get(header_name())
+            .and_then(RequestId::parse)
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Creates an endpoint which returns the identifier of the request.
+///
+/// See [the module level documentation](./index.html) for details.
+pub fn request_id() -> RequestIdEndpoint {
+    RequestIdEndpoint { _priv: () }
+}
+
+#[allow(missing_docs)]
+#[derive(Debug)]
+pub struct RequestIdEndpoint {
+    _priv: (),
+}
+
+impl<'a> Endpoint<'a> for RequestIdEndpoint {
+    type Output = (RequestId,);
+    type Future = future::FutureResult<Self::Output, Error>;
+
+    fn apply(&'a self, cx: &mut ApplyContext<'_>) -> ApplyResult<Self::Future> {
+        let id = match RequestId::from_input(cx.input()) {
+            Some(id) => id,
+            None => {
+                let id = cx
+                    .input()
+                    .headers()
+                    .get(header_name())
+                    .and_then(RequestId::parse)
+                    .unwrap_or_else(RequestId::generate);
+                let value =
+                    HeaderValue::from_str(id.as_str()).expect("should be a valid header value");
+                cx.input().response_headers().insert(header_name(), value);
+                id
+            }
+        };
+        Ok(future::ok((id,)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use finchers::prelude::*;
+    use finchers::test;
+    use http::Request;
+    use juniper::tests::model::Database;
+
+    use super::{request_id, RequestId};
+    use execute::current_thread;
+    use testing::{database_schema, json_body};
+
+    #[test]
+    fn test_generate() {
+        let id = RequestId::generate();
+        assert_eq!(id.as_str().len(), 32);
+        assert_ne!(id, RequestId::generate());
+    }
+
+    #[test]
+    fn test_request_id() {
+        let schema = database_schema();
+        let endpoint = request_id()
+            .map(|_: RequestId| Database::new())
+            .wrap(current_thread(schema));
+        let mut runner = test::runner(endpoint);
+
+        let response = runner
+            .perform(Request::get("/?query={hero{name}}").header("x-request-id", "abc-123"))
+            .unwrap();
+        assert_eq!(response.headers().get("x-request-id").unwrap(), "abc-123");
+        let body = json_body(&response);
+        assert_eq!(body["extensions"]["requestId"], "abc-123");
+
+        let response = runner
+            .perform(Request::get("/?query={hero{name}}").header("x-request-id", "a b"))
+            .unwrap();
+        let id = response.headers().get("x-request-id").unwrap();
+        assert_eq!(id.len(), 32);
+        let body = json_body(&response);
+        assert_eq!(body["extensions"]["requestId"], id.to_str().unwrap());
+    }
+}
@@ -0,0 +1,190 @@
+//! Fixtures shared by the tests in this crate.
+
+use finchers::endpoint;
+use finchers::endpoint::{ApplyContext, ApplyResult, Endpoint};
+use finchers::error::Error;
+use finchers::prelude::*;
+use finchers::test::TestResult;
+
+use futures::{Async, Future, Poll};
+use http::Response;
+use juniper;
+use juniper::tests::model::Database;
+use juniper::{EmptyMutation, RootNode};
+use serde_json;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use execute::cancel;
+use request::{graphql_request, GraphQLRequest, GraphQLResponse};
+
+pub(crate) type DatabaseSchema = RootNode<'static, Database, EmptyMutation<Database>>;
+
+/// Creates the schema of the Star Wars example provided by Juniper.
+pub(crate) fn database_schema() -> DatabaseSchema {
+    RootNode::new(Database::new(), EmptyMutation::new())
+}
+
+/// An endpoint which creates the context of `database_schema` for each request.
+pub(crate) fn database() -> impl for<'a> Endpoint<'a, Output = (Database,)> {
+    endpoint::unit().map(Database::new)
+}
+
+/// An endpoint which receives a GraphQL request and executes it on the current thread,
+/// without an executor.
+pub(crate) fn execute_request() -> impl for<'a> Endpoint<'a, Output = (GraphQLResponse,)> {
+    let schema = database_schema();
+    graphql_request().map(move |request: GraphQLRequest| request.execute(&schema, &Database::new()))
+}
+
+/// Parses the body of the response as JSON.
+pub(crate) fn json_body(response: &Response<TestResult>) -> serde_json::Value {
+    serde_json::from_str(&response.body().to_utf8().unwrap()).unwrap()
+}
+
+/// A context which lets the tests observe and control the execution of the resolvers
+/// in `ProbeQuery`.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Probe {
+    inner: Arc<ProbeInner>,
+}
+
+#[derive(Debug, Default)]
+struct ProbeInner {
+    started: AtomicUsize,
+    cancelled: AtomicUsize,
+    released: AtomicBool,
+}
+
+impl juniper::Context for Probe {}
+
+impl Probe {
+    pub(crate) fn new() -> Probe {
+        Probe::default()
+    }
+
+    /// Blocks the current thread until `n` resolvers have started waiting.
+    pub(crate) fn wait_started(&self, n: usize) {
+        while self.inner.started.load(Ordering::SeqCst) < n {
+            thread::yield_now();
+        }
+    }
+
+    /// Lets the waiting resolvers return.
+    pub(crate) fn release(&self) {
+        self.inner.released.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns the number of resolvers which have observed the cancellation.
+    pub(crate) fn cancelled(&self) -> usize {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Blocks the current thread until `n` resolvers have observed the cancellation.
+    pub(crate) fn wait_cancelled(&self, n: usize) {
+        while self.cancelled() < n {
+            thread::yield_now();
+        }
+    }
+
+    // Waits until released, or cancelled if `cancellable` is `true`.
+    fn wait(&self, cancellable: bool) -> bool {
+        self.inner.started.fetch_add(1, Ordering::SeqCst);
+        loop {
+            if cancellable && cancel::current().is_cancelled() {
+                self.inner.cancelled.fetch_add(1, Ordering::SeqCst);
+                return false;
+            }
+            if self.inner.released.load(Ordering::SeqCst) {
+                return true;
+            }
+            thread::yield_now();
+        }
+    }
+}
+
+pub(crate) struct ProbeQuery;
+
+graphql_object!(ProbeQuery: Probe |&self| {
+    field ready() -> bool {
+        true
+    }
+
+    field wait(&executor) -> bool {
+        executor.context().wait(true)
+    }
+
+    field block(&executor) -> bool {
+        executor.context().wait(false)
+    }
+
+    field panic() -> bool {
+        panic!("resolver panicked")
+    }
+});
+
+pub(crate) type ProbeSchema = RootNode<'static, ProbeQuery, EmptyMutation<Probe>>;
+
+pub(crate) fn probe_schema() -> ProbeSchema {
+    RootNode::new(ProbeQuery, EmptyMutation::new())
+}
+
+/// An endpoint which polls the future of the inner endpoint only once and then drops it,
+/// as if the client has gone away before receiving the response.
+///
+/// The specified function is called between the poll and the drop, e.g. for waiting until
+/// the spawned task starts.  The output is `true` if the inner future has completed
+/// in the first poll.
+#[derive(Debug)]
+pub(crate) struct Abandon<E, F> {
+    endpoint: E,
+    before_drop: F,
+}
+
+impl<E, F> Abandon<E, F> {
+    pub(crate) fn new(endpoint: E, before_drop: F) -> Abandon<E, F> {
+        Abandon {
+            endpoint,
+            before_drop,
+        }
+    }
+}
+
+impl<'a, E, F> Endpoint<'a> for Abandon<E, F>
+where
+    E: Endpoint<'a>,
+    F: Fn() + 'a,
+{
+    type Output = (bool,);
+    type Future = AbandonFuture<'a, E::Future, F>;
+
+    fn apply(&'a self, cx: &mut ApplyContext<'_>) -> ApplyResult<Self::Future> {
+        Ok(AbandonFuture {
+            future: Some(self.endpoint.apply(cx)?),
+            before_drop: &self.before_drop,
+        })
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub(crate) struct AbandonFuture<'a, Fut, F> {
+    future: Option<Fut>,
+    before_drop: &'a F,
+}
+
+impl<'a, Fut, F> Future for AbandonFuture<'a, Fut, F>
+where
+    Fut: Future<Error = Error>,
+    F: Fn(),
+{
+    type Item = (bool,);
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let mut future = self.future.take().expect("the future has already polled");
+        let is_ready = future.poll()?.is_ready();
+        (self.before_drop)();
+        Ok(Async::Ready((is_ready,)))
+    }
+}
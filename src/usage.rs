@@ -0,0 +1,267 @@
+//! Usage statistics of the fields in a GraphQL schema.
+//!
+//! The executors record the fields selected by each executed operation into the registry
+//! specified by [`Custom::field_usage`], by walking the document against the schema.
+//! A field is counted once per operation, even if it is selected multiple times.
+//! The endpoint created by [`field_usage_endpoint`] dumps the statistics as JSON,
+//! in the following format:
+//!
+//! ```json
+//! {
+//!   "fields": [
+//!     {
+//!       "type": "Human",
+//!       "field": "homePlanet",
+//!       "count": 42,
+//!       "isDeprecated": true,
+//!       "deprecationReason": "Use `planet` instead"
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! [`Custom::field_usage`]: ../execute/struct.Custom.html#method.field_usage
+//! [`field_usage_endpoint`]: ./fn.field_usage_endpoint.html
+
+use finchers::endpoint::{ApplyContext, ApplyResult, Endpoint};
+use finchers::error;
+use finchers::error::Error;
+
+use futures::{Future, Poll};
+
+use http::{header, Response};
+use juniper::meta::{Field, InterfaceMeta, MetaType, ObjectMeta};
+use juniper::{GraphQLType, RootNode};
+use serde_json;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use request::SingleRequest;
+
+/// A registry of the usage statistics of fields.
+#[derive(Debug, Default)]
+pub struct FieldUsage {
+    fields: Mutex<BTreeMap<(String, String), FieldStats>>,
+}
+
+impl FieldUsage {
+    /// Creates an empty `FieldUsage`.
+    ///
+    /// The statistics contain only the fields which have been used at least once.
+    pub fn new() -> FieldUsage {
+        FieldUsage::default()
+    }
+
+    /// Creates a `FieldUsage` which contains all fields of the object and interface types
+    /// in the specified schema, so that the unused fields are also reported.
+    pub fn with_schema<QueryT, MutationT>(
+        root_node: &RootNode<'static, QueryT, MutationT>,
+    ) -> FieldUsage
+    where
+        QueryT: GraphQLType,
+        MutationT: GraphQLType,
+    {
+        let mut fields = BTreeMap::new();
+        for meta_type in root_node.schema.concrete_type_list() {
+            let type_name = match meta_type.name() {
+                Some(name) if !name.starts_with("__") => name,
+                _ => continue,
+            };
+            let type_fields = match *meta_type {
+                MetaType::Object(ObjectMeta { ref fields, .. })
+                | MetaType::Interface(InterfaceMeta { ref fields, .. }) => fields,
+                _ => continue,
+            };
+            for field in type_fields {
+                let stats = FieldStats::new(type_name, field);
+                fields.insert((type_name.to_owned(), field.name.clone()), stats);
+            }
+        }
+        FieldUsage {
+            fields: Mutex::new(fields),
+        }
+    }
+
+    /// Returns the statistics of the specified field, if available.
+    pub fn get(&self, type_name: &str, field_name: &str) -> Option<FieldStats> {
+        self.fields
+            .lock()
+            .unwrap()
+            .get(&(type_name.to_owned(), field_name.to_owned()))
+            .cloned()
+    }
+
+    /// Returns the statistics of all fields, ordered by the type name and the field name.
+    pub fn snapshot(&self) -> Vec<FieldStats> {
+        self.fields.lock().unwrap().values().cloned().collect()
+    }
+
+    /// Returns the statistics of all fields as a JSON value.
+    pub fn to_json(&self) -> serde_json::Value {
+        json!({ "fields": self.snapshot() })
+    }
+
+    pub(crate) fn record<QueryT, MutationT>(
+        &self,
+        request: &SingleRequest,
+        root_node: &RootNode<'static, QueryT, MutationT>,
+    ) where
+        QueryT: GraphQLType,
+        MutationT: GraphQLType,
+    {
+        let document = match request.document() {
+            Some(document) => document,
+            None => return,
+        };
+        let operation = match document.operation(request.operation_name()) {
+            Some(operation) => operation,
+            None => return,
+        };
+
+        let mut used = HashSet::new();
+        let mut fields = self.fields.lock().unwrap();
+        document.visit_fields(operation, root_node, |parent, field, _| {
+            let type_name = match parent.name() {
+                Some(name) => name,
+                None => return,
+            };
+            let key = (type_name.to_owned(), field.name.clone());
+            if used.insert(key.clone()) {
+                fields
+                    .entry(key)
+                    .or_insert_with(|| FieldStats::new(type_name, field))
+                    .count += 1;
+            }
+        });
+    }
+}
+
+/// The usage statistics of a field.
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldStats {
+    #[serde(rename = "type")]
+    type_name: String,
+    #[serde(rename = "field")]
+    field_name: String,
+    count: u64,
+    #[serde(rename = "isDeprecated")]
+    is_deprecated: bool,
+    #[serde(rename = "deprecationReason")]
+    deprecation_reason: Option<String>,
+}
+
+impl FieldStats {
+    fn new(type_name: &str, field: &Field<'_>) -> FieldStats {
+        FieldStats {
+            type_name: type_name.to_owned(),
+            field_name: field.name.clone(),
+            count: 0,
+            is_deprecated: field.deprecation_reason.is_some(),
+            deprecation_reason: field.deprecation_reason.clone(),
+        }
+    }
+
+    /// Returns the name of the type which has this field.
+    pub fn type_name(&self) -> &str {
+        &self.type_name
+    }
+
+    /// Returns the name of this field.
+    pub fn field_name(&self) -> &str {
+        &self.field_name
+    }
+
+    /// Returns the number of executed operations which selected this field.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Returns `true` if this field is marked as deprecated.
+    pub fn is_deprecated(&self) -> bool {
+        self.is_deprecated
+    }
+
+    /// Returns the reason of the deprecation, if available.
+    pub fn deprecation_reason(&self) -> Option<&str> {
+        self.deprecation_reason.as_ref().map(String::as_str)
+    }
+}
+
+/// Creates an endpoint which dumps the specified usage statistics as JSON.
+pub fn field_usage_endpoint(field_usage: Arc<FieldUsage>) -> FieldUsageEndpoint {
+    FieldUsageEndpoint { field_usage }
+}
+
+#[allow(missing_docs)]
+#[derive(Debug)]
+pub struct FieldUsageEndpoint {
+    field_usage: Arc<FieldUsage>,
+}
+
+impl<'a> Endpoint<'a> for FieldUsageEndpoint {
+    type Output = (Response<Vec<u8>>,);
+    type Future = FieldUsageFuture<'a>;
+
+    fn apply(&'a self, _: &mut ApplyContext<'_>) -> ApplyResult<Self::Future> {
+        Ok(FieldUsageFuture(&self.field_usage))
+    }
+}
+
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct FieldUsageFuture<'a>(&'a FieldUsage);
+
+impl<'a> Future for FieldUsageFuture<'a> {
+    type Item = (Response<Vec<u8>>,);
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let body = serde_json::to_vec(&self.0.to_json()).map_err(error::fail)?;
+        Ok((Response::builder()
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(body)
+            .expect("should be a valid response"),)
+            .into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use finchers::prelude::*;
+    use finchers::test;
+    use http::Request;
+    use std::sync::Arc;
+
+    use super::{field_usage_endpoint, FieldUsage};
+    use execute::current_thread;
+    use testing::{database, database_schema, json_body};
+
+    #[test]
+    fn test_field_usage() {
+        let schema = database_schema();
+        let field_usage = Arc::new(FieldUsage::with_schema(&schema));
+        let endpoint = database().wrap(current_thread(schema).field_usage(field_usage.clone()));
+        let mut runner = test::runner(endpoint);
+
+        runner
+            .perform(Request::get("/?query={hero{name%20a:name}}"))
+            .unwrap();
+        runner.perform(Request::get("/?query={hero{id}}")).unwrap();
+
+        assert_eq!(field_usage.get("Query", "hero").unwrap().count(), 2);
+        assert_eq!(field_usage.get("Character", "name").unwrap().count(), 1);
+        assert_eq!(field_usage.get("Query", "human").unwrap().count(), 0);
+        assert!(field_usage.get("__Schema", "types").is_none());
+
+        let mut runner = test::runner(field_usage_endpoint(field_usage.clone()));
+        let response = runner.perform("/").unwrap();
+        let body = json_body(&response);
+        assert!(body["fields"].as_array().unwrap().contains(&json!({
+            "type": "Query",
+            "field": "hero",
+            "count": 2,
+            "isDeprecated": false,
+            "deprecationReason": null,
+        })));
+    }
+}
@@ -1,23 +1,31 @@
 extern crate finchers;
 extern crate finchers_juniper;
+extern crate futures;
 extern crate juniper;
 #[macro_use]
 extern crate percent_encoding;
 extern crate http;
 
 use finchers::endpoint::syntax;
+use finchers::error::Error;
 use finchers::prelude::*;
 use finchers::test;
 use finchers::test::{TestResult, TestRunner};
+use finchers_juniper::execute;
 use finchers_juniper::request::{GraphQLRequest, GraphQLResponse};
 
+use futures::future;
+use futures::Future;
+
 use juniper::http::tests as http_tests;
 use juniper::tests::model::Database;
 use juniper::{EmptyMutation, RootNode};
 
-use http::{Request, Response};
+use http::{Request, Response, StatusCode};
 use percent_encoding::{utf8_percent_encode, QUERY_ENCODE_SET};
 use std::cell::RefCell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 
 type Schema = RootNode<'static, Database, EmptyMutation<Database>>;
 
@@ -91,3 +99,82 @@ fn test_finchers_integration() {
     };
     http_tests::run_http_test_suite(&integration);
 }
+
+#[test]
+fn test_thread_pool_executor() {
+    let schema = Schema::new(Database::new(), EmptyMutation::<Database>::new());
+    let executor = execute::thread_pool(schema, execute::ThreadPoolConfig::new().pool_size(2));
+    let pool = executor.handle();
+    let endpoint = syntax::eos()
+        .and(endpoint::unit().map(Database::new))
+        .wrap(executor);
+    let integration = TestFinchersIntegration {
+        runner: RefCell::new(test::runner(endpoint)),
+    };
+    http_tests::run_http_test_suite(&integration);
+
+    let stats = pool.worker_stats();
+    assert_eq!(stats.len(), 2);
+    assert!(stats.iter().all(|stats| !stats.is_busy()));
+    assert!(
+        stats
+            .iter()
+            .map(|stats| stats.completed_tasks())
+            .sum::<u64>()
+            > 0
+    );
+
+    pool.shutdown().wait().unwrap();
+    assert_eq!(pool.running_workers(), 0);
+}
+
+#[test]
+fn test_graphql_task_inline() {
+    let schema = Arc::new(Schema::new(
+        Database::new(),
+        EmptyMutation::<Database>::new(),
+    ));
+    let request = GraphQLRequest::single("{ hero { name } }".into(), None, None);
+    let task = execute::GraphQLTask::new(request, schema.clone(), Database::new());
+    assert_eq!(task.wait().unwrap().status(), StatusCode::OK);
+
+    let request = GraphQLRequest::single("{ hero { name } }".into(), None, None);
+    let task = execute::GraphQLTask::new(request, schema, Database::new());
+    task.cancellation_token().cancel();
+    assert!(task.wait().is_err());
+}
+
+struct CountingStrategy {
+    count: Arc<AtomicUsize>,
+}
+
+impl execute::ExecutionStrategy<Schema, Database> for CountingStrategy {
+    type Future = future::FutureResult<GraphQLResponse, Error>;
+
+    fn execute(
+        &self,
+        request: GraphQLRequest,
+        context: Database,
+        schema: Arc<Schema>,
+    ) -> Self::Future {
+        self.count.fetch_add(1, Ordering::SeqCst);
+        future::ok(request.execute(&schema, &context))
+    }
+}
+
+#[test]
+fn test_custom_strategy() {
+    let schema = Schema::new(Database::new(), EmptyMutation::<Database>::new());
+    let count = Arc::new(AtomicUsize::new(0));
+    let strategy = CountingStrategy {
+        count: count.clone(),
+    };
+    let endpoint = syntax::eos()
+        .and(endpoint::unit().map(Database::new))
+        .wrap(execute::custom(schema, strategy));
+    let integration = TestFinchersIntegration {
+        runner: RefCell::new(test::runner(endpoint)),
+    };
+    http_tests::run_http_test_suite(&integration);
+    assert!(count.load(Ordering::SeqCst) > 0);
+}